@@ -2,6 +2,11 @@ pub use sea_orm_migration::prelude::*;
 
 mod m20230402_214115_create_users_table;
 mod m20230405_131126_create_todos_table;
+mod m20261019_090000_add_email_verification_to_users;
+mod m20261019_100000_create_organizations_table;
+mod m20261019_110000_create_attachments_table;
+mod m20261019_120000_create_webhooks_table;
+mod m20261019_130000_create_user_identities_table;
 
 pub struct Migrator;
 
@@ -11,6 +16,11 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20230402_214115_create_users_table::Migration),
             Box::new(m20230405_131126_create_todos_table::Migration),
+            Box::new(m20261019_090000_add_email_verification_to_users::Migration),
+            Box::new(m20261019_100000_create_organizations_table::Migration),
+            Box::new(m20261019_110000_create_attachments_table::Migration),
+            Box::new(m20261019_120000_create_webhooks_table::Migration),
+            Box::new(m20261019_130000_create_user_identities_table::Migration),
         ]
     }
 }
@@ -0,0 +1,70 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum User {
+    Table,
+    EmailVerifiedAt,
+    PendingEmail,
+    EmailVerificationToken,
+    EmailVerificationSentAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only accepts one column per ALTER TABLE statement.
+        for mut column in [
+            ColumnDef::new(User::EmailVerifiedAt)
+                .timestamp()
+                .null()
+                .to_owned(),
+            ColumnDef::new(User::PendingEmail)
+                .string()
+                .null()
+                .to_owned(),
+            ColumnDef::new(User::EmailVerificationToken)
+                .string()
+                .null()
+                .to_owned(),
+            ColumnDef::new(User::EmailVerificationSentAt)
+                .timestamp()
+                .null()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            User::EmailVerifiedAt,
+            User::PendingEmail,
+            User::EmailVerificationToken,
+            User::EmailVerificationSentAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
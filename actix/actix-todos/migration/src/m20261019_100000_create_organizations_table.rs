@@ -0,0 +1,137 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum Organization {
+    Table,
+    Id,
+    Name,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum OrganizationMember {
+    Table,
+    Id,
+    OrganizationId,
+    UserId,
+    Role,
+}
+
+#[derive(Iden)]
+enum Todo {
+    Table,
+    OrganizationId,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Organization::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Organization::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Organization::Name).string().not_null())
+                    .col(ColumnDef::new(Organization::CreatedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OrganizationMember::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OrganizationMember::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OrganizationMember::OrganizationId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OrganizationMember::UserId).integer().not_null())
+                    .col(ColumnDef::new(OrganizationMember::Role).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_organization_member_unique")
+                    .table(OrganizationMember::Table)
+                    .col(OrganizationMember::OrganizationId)
+                    .col(OrganizationMember::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Todo::Table)
+                    .add_column(
+                        ColumnDef::new(Todo::OrganizationId)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Every existing user gets a personal organisation with the same id,
+        // which then owns the todos they created.
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"INSERT INTO "organization" ("id", "name", "created_at")
+               SELECT "id", "name", CURRENT_TIMESTAMP FROM "user""#,
+        )
+        .await?;
+        db.execute_unprepared(
+            r#"INSERT INTO "organization_member" ("organization_id", "user_id", "role")
+               SELECT "id", "id", 'owner' FROM "user""#,
+        )
+        .await?;
+        db.execute_unprepared(r#"UPDATE "todo" SET "organization_id" = "user_id""#)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Todo::Table)
+                    .drop_column(Todo::OrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(OrganizationMember::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Organization::Table).to_owned())
+            .await
+    }
+}
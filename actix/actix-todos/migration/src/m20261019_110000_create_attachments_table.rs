@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum Attachment {
+    Table,
+    Id,
+    TodoId,
+    UserId,
+    Filename,
+    ContentType,
+    Size,
+    StorageKey,
+    CreatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Attachment::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Attachment::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Attachment::TodoId).integer().not_null())
+                    .col(ColumnDef::new(Attachment::UserId).integer().not_null())
+                    .col(ColumnDef::new(Attachment::Filename).string().not_null())
+                    .col(ColumnDef::new(Attachment::ContentType).string().not_null())
+                    .col(ColumnDef::new(Attachment::Size).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Attachment::StorageKey)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Attachment::CreatedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_attachment_todo_id")
+                    .table(Attachment::Table)
+                    .col(Attachment::TodoId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Attachment::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,100 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum Webhook {
+    Table,
+    Id,
+    UserId,
+    OrganizationId,
+    Url,
+    Secret,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum WebhookDelivery {
+    Table,
+    Id,
+    WebhookId,
+    Event,
+    Payload,
+    Attempt,
+    StatusCode,
+    Error,
+    Success,
+    CreatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Webhook::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Webhook::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Webhook::UserId).integer().not_null())
+                    .col(ColumnDef::new(Webhook::OrganizationId).integer().not_null())
+                    .col(ColumnDef::new(Webhook::Url).string().not_null())
+                    .col(ColumnDef::new(Webhook::Secret).string().not_null())
+                    .col(ColumnDef::new(Webhook::CreatedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookDelivery::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WebhookDelivery::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(WebhookDelivery::WebhookId).integer().not_null())
+                    .col(ColumnDef::new(WebhookDelivery::Event).string().not_null())
+                    .col(ColumnDef::new(WebhookDelivery::Payload).text().not_null())
+                    .col(ColumnDef::new(WebhookDelivery::Attempt).integer().not_null())
+                    .col(ColumnDef::new(WebhookDelivery::StatusCode).integer().null())
+                    .col(ColumnDef::new(WebhookDelivery::Error).string().null())
+                    .col(ColumnDef::new(WebhookDelivery::Success).boolean().not_null())
+                    .col(ColumnDef::new(WebhookDelivery::CreatedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webhook_delivery_webhook_id")
+                    .table(WebhookDelivery::Table)
+                    .col(WebhookDelivery::WebhookId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebhookDelivery::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Webhook::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Learn more at https://docs.rs/sea-query#iden
+#[derive(Iden)]
+enum UserIdentity {
+    Table,
+    Id,
+    UserId,
+    Issuer,
+    Subject,
+    CreatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserIdentity::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserIdentity::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserIdentity::UserId).integer().not_null())
+                    .col(ColumnDef::new(UserIdentity::Issuer).string().not_null())
+                    .col(ColumnDef::new(UserIdentity::Subject).string().not_null())
+                    .col(ColumnDef::new(UserIdentity::CreatedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_identity_issuer_subject")
+                    .table(UserIdentity::Table)
+                    .col(UserIdentity::Issuer)
+                    .col(UserIdentity::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserIdentity::Table).to_owned())
+            .await
+    }
+}
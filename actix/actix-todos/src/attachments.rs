@@ -0,0 +1,557 @@
+use actix_multipart::Multipart;
+use actix_web::{
+    delete,
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorPayloadTooLarge},
+    get,
+    http::header::{self, ContentDisposition, ContentType, DispositionParam, DispositionType},
+    post,
+    web::{Bytes, Data, Path},
+    HttpResponse, Result,
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use futures_util::StreamExt;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    ModelTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use serde_json::json;
+
+use crate::{
+    auth::claims_from_extractor,
+    blobs::{self, BlobStore},
+    config::Config,
+    entity::prelude::{Attachment, Todo},
+    entity::{attachment, todo},
+    organizations::{find_membership, ROLE_OWNER},
+    AppState,
+};
+
+#[derive(serde::Serialize)]
+pub struct ResponseAttachment {
+    id: i32,
+    todo_id: i32,
+    filename: String,
+    content_type: String,
+    size: i64,
+    created_at: Option<String>,
+}
+
+impl From<attachment::Model> for ResponseAttachment {
+    fn from(attachment: attachment::Model) -> Self {
+        ResponseAttachment {
+            id: attachment.id,
+            todo_id: attachment.todo_id,
+            filename: attachment.filename,
+            content_type: attachment.content_type,
+            size: attachment.size,
+            created_at: attachment.created_at,
+        }
+    }
+}
+
+/// Stores every file part of the multipart body as an attachment of the todo,
+/// up to `max_attachments_per_upload` of them, or none when any part is
+/// rejected. Parts without a filename are ignored.
+#[post("/todos/{id}/attachments")]
+pub async fn upload(
+    path: Path<i32>,
+    mut payload: Multipart,
+    auth: BearerAuth,
+    state: Data<AppState>,
+    config: Data<Config>,
+) -> Result<HttpResponse> {
+    let claims = claims_from_extractor(auth);
+    let todo = match find_todo(&state.db, claims.org, path.into_inner()).await? {
+        Some(todo) => todo,
+        None => return Ok(todo_not_found()),
+    };
+
+    // Every part is read and checked before anything is stored, so a rejected
+    // upload leaves no attachments behind.
+    let mut files = Vec::new();
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(ErrorBadRequest)?;
+        let filename = match field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .and_then(sanitize_filename)
+        {
+            Some(filename) => filename,
+            None => continue,
+        };
+        if files.len() == config.max_attachments_per_upload {
+            return Err(ErrorPayloadTooLarge("too many attachments in one upload"));
+        }
+        let content_type = field
+            .content_type()
+            .map(|mime| mime.to_string())
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+
+        let data = field
+            .bytes(config.max_attachment_size)
+            .await
+            .map_err(|_| ErrorPayloadTooLarge("attachment too large"))?
+            .map_err(ErrorBadRequest)?;
+
+        files.push((filename, content_type, data));
+    }
+
+    if files.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": "no files uploaded" })));
+    }
+
+    let mut keys = Vec::new();
+    match store_attachments(&state, &todo, claims.sub as i32, files, &mut keys).await {
+        Ok(attachments) => Ok(HttpResponse::Ok().json(json!({ "attachments": attachments }))),
+        Err(e) => {
+            remove_blobs(state.blobs.as_ref(), &keys).await;
+            Err(e)
+        }
+    }
+}
+
+/// Writes the blobs and inserts their rows in one transaction. The keys of
+/// the blobs written so far are pushed to `keys`, for the caller to remove
+/// when this fails.
+async fn store_attachments(
+    state: &AppState,
+    todo: &todo::Model,
+    user_id: i32,
+    files: Vec<(String, String, Bytes)>,
+    keys: &mut Vec<String>,
+) -> Result<Vec<ResponseAttachment>> {
+    let txn = state.db.begin().await.map_err(ErrorInternalServerError)?;
+
+    let mut attachments = Vec::new();
+    for (filename, content_type, data) in files {
+        let key = blobs::new_key();
+        state
+            .blobs
+            .put(&key, &data)
+            .await
+            .map_err(ErrorInternalServerError)?;
+        keys.push(key.clone());
+
+        let attachment = attachment::ActiveModel {
+            todo_id: Set(todo.id),
+            user_id: Set(user_id),
+            filename: Set(filename),
+            content_type: Set(content_type),
+            size: Set(data.len() as i64),
+            storage_key: Set(key),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+        attachments.push(ResponseAttachment::from(attachment));
+    }
+
+    txn.commit().await.map_err(ErrorInternalServerError)?;
+
+    Ok(attachments)
+}
+
+#[get("/todos/{id}/attachments")]
+pub async fn list(
+    path: Path<i32>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let organization_id = claims_from_extractor(auth).org;
+    let todo = match find_todo(&state.db, organization_id, path.into_inner()).await? {
+        Some(todo) => todo,
+        None => return Ok(todo_not_found()),
+    };
+
+    let attachments: Vec<_> = todo
+        .find_related(Attachment)
+        .order_by_asc(attachment::Column::Id)
+        .all(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(ResponseAttachment::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({ "attachments": attachments })))
+}
+
+#[get("/attachments/{id}")]
+pub async fn download(
+    path: Path<i32>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let organization_id = claims_from_extractor(auth).org;
+    let attachment = match find_attachment(&state.db, organization_id, path.into_inner()).await? {
+        Some(attachment) => attachment,
+        None => return Ok(attachment_not_found()),
+    };
+
+    let data = state
+        .blobs
+        .get(&attachment.storage_key)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    // The type comes from the uploader, browsers must neither sniff nor
+    // render it inline.
+    let content_type = attachment
+        .content_type
+        .parse()
+        .map(ContentType)
+        .unwrap_or_else(|_| ContentType::octet_stream());
+
+    Ok(HttpResponse::Ok()
+        .insert_header(content_type)
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(attachment.filename)],
+        })
+        .body(data))
+}
+
+/// Only the uploader and the organisation's owners may delete an attachment.
+#[delete("/attachments/{id}")]
+pub async fn remove(
+    path: Path<i32>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let claims = claims_from_extractor(auth);
+    let attachment = match find_attachment(&state.db, claims.org, path.into_inner()).await? {
+        Some(attachment) => attachment,
+        None => return Ok(attachment_not_found()),
+    };
+
+    if attachment.user_id != claims.sub as i32 {
+        let membership = find_membership(&state.db, claims.sub as i32, claims.org)
+            .await
+            .map_err(ErrorInternalServerError)?;
+        if !matches!(membership, Some(membership) if membership.role == ROLE_OWNER) {
+            return Ok(HttpResponse::Forbidden()
+                .json(json!({ "error": "only the uploader or an owner can delete this" })));
+        }
+    }
+
+    let key = attachment.storage_key.clone();
+    attachment
+        .delete(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    remove_blobs(state.blobs.as_ref(), &[key]).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Deletes the attachment rows of `todo_ids` and returns their storage keys.
+/// Pass the keys to [`remove_blobs`] once the surrounding transaction has
+/// committed.
+pub async fn delete_for_todos<C: ConnectionTrait>(
+    db: &C,
+    todo_ids: Vec<i32>,
+) -> Result<Vec<String>, DbErr> {
+    if todo_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let attachments = Attachment::find()
+        .filter(attachment::Column::TodoId.is_in(todo_ids.clone()))
+        .all(db)
+        .await?;
+    Attachment::delete_many()
+        .filter(attachment::Column::TodoId.is_in(todo_ids))
+        .exec(db)
+        .await?;
+
+    Ok(attachments
+        .into_iter()
+        .map(|attachment| attachment.storage_key)
+        .collect())
+}
+
+/// Best effort: the rows are already gone, so a blob that cannot be deleted
+/// is only logged.
+pub async fn remove_blobs(blobs: &dyn BlobStore, keys: &[String]) {
+    for key in keys {
+        if let Err(e) = blobs.delete(key).await {
+            log::warn!("failed to delete blob {key}: {e}");
+        }
+    }
+}
+
+async fn find_todo(
+    db: &DatabaseConnection,
+    organization_id: i32,
+    id: i32,
+) -> Result<Option<todo::Model>> {
+    Todo::find_by_id(id)
+        .filter(todo::Column::OrganizationId.eq(organization_id))
+        .one(db)
+        .await
+        .map_err(ErrorInternalServerError)
+}
+
+async fn find_attachment(
+    db: &DatabaseConnection,
+    organization_id: i32,
+    id: i32,
+) -> Result<Option<attachment::Model>> {
+    Attachment::find_by_id(id)
+        .inner_join(Todo)
+        .filter(todo::Column::OrganizationId.eq(organization_id))
+        .one(db)
+        .await
+        .map_err(ErrorInternalServerError)
+}
+
+/// Keeps the last path component of a client supplied filename and drops
+/// control characters.
+fn sanitize_filename(filename: &str) -> Option<String> {
+    let name: String = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    let name = name.trim();
+
+    if name.is_empty() || name == "." || name == ".." {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+fn todo_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": "todo not found" }))
+}
+
+fn attachment_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": "attachment not found" }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{download, sanitize_filename, upload};
+    use crate::{
+        auth::{encode_jwt, verify_jwt},
+        config::{Config, CorsConfig},
+        entity::prelude::Attachment,
+        entity::{todo, user},
+        organizations,
+        test_util::{self, TempBlobStore},
+        AppState,
+    };
+    use actix_web::{
+        http::{header, StatusCode},
+        test as actix_test, web, App,
+    };
+    use actix_web_httpauth::middleware::HttpAuthentication;
+    use sea_orm::{ActiveModelTrait, EntityTrait, PaginatorTrait, Set};
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    const BOUNDARY: &str = "attachment-test-boundary";
+
+    fn config() -> Config {
+        Config {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            workers: None,
+            tls: None,
+            shutdown_timeout: 30,
+            todo_cache_ttl: 30,
+            attachments_dir: std::env::temp_dir(),
+            max_attachment_size: 1024,
+            max_attachments_per_upload: 2,
+            public_url: test_util::PUBLIC_URL.to_string(),
+            cors: CorsConfig {
+                allowed_origins: Vec::new(),
+                allowed_methods: Vec::new(),
+                allow_credentials: false,
+                max_age: 0,
+            },
+            oidc: None,
+            mail: None,
+        }
+    }
+
+    /// A user with a todo in their own organisation, and a token for it.
+    async fn state() -> (AppState, i32, String) {
+        let state = test_util::state().await;
+        let db = &state.db;
+
+        let user = user::ActiveModel {
+            name: Set("a".to_string()),
+            email: Set("a@example.com".to_string()),
+            password: Set(String::new()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+        let organization = organizations::create_organization(db, "a", user.id)
+            .await
+            .unwrap();
+        let todo = todo::ActiveModel {
+            user_id: Set(user.id),
+            organization_id: Set(organization.id),
+            name: Set("todo".to_string()),
+            status: Set(false),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+        let token = encode_jwt(user.id as u32, organization.id).unwrap();
+
+        (state, todo.id, token)
+    }
+
+    fn multipart(files: &[(&str, &str, &str)]) -> String {
+        let mut body = String::new();
+        for (filename, content_type, content) in files {
+            body.push_str(&format!(
+                "--{BOUNDARY}\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\n\
+                 Content-Type: {content_type}\r\n\r\n\
+                 {content}\r\n"
+            ));
+        }
+        body.push_str(&format!("--{BOUNDARY}--\r\n"));
+        body
+    }
+
+    macro_rules! app {
+        ($state:expr) => {
+            actix_test::init_service(
+                App::new()
+                    .app_data(web::Data::new($state.clone()))
+                    .app_data(web::Data::new(config()))
+                    .service(
+                        web::scope("/api")
+                            .wrap(HttpAuthentication::bearer(verify_jwt))
+                            .service(upload)
+                            .service(download),
+                    ),
+            )
+            .await
+        };
+    }
+
+    fn upload_request(todo_id: i32, token: &str, body: String) -> actix_test::TestRequest {
+        actix_test::TestRequest::post()
+            .uri(&format!("/api/todos/{todo_id}/attachments"))
+            .insert_header((header::AUTHORIZATION, format!("Bearer {token}")))
+            .insert_header((
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            ))
+            .set_payload(body)
+    }
+
+    #[actix_web::test]
+    async fn test_upload_rejects_too_many_files() {
+        let (state, todo_id, token) = state().await;
+        let app = app!(state);
+
+        let body = multipart(&[
+            ("a.txt", "text/plain", "a"),
+            ("b.txt", "text/plain", "b"),
+            ("c.txt", "text/plain", "c"),
+        ]);
+        let res =
+            actix_test::call_service(&app, upload_request(todo_id, &token, body).to_request())
+                .await;
+
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_web::test]
+    async fn test_rejected_upload_stores_nothing() {
+        let (mut state, todo_id, token) = state().await;
+        let blobs = Arc::new(TempBlobStore::default());
+        state.blobs = blobs.clone();
+        let app = app!(state);
+
+        let too_large = "x".repeat(2048);
+        for files in [
+            [
+                ("a.txt", "text/plain", "a"),
+                ("b.txt", "text/plain", "b"),
+                ("c.txt", "text/plain", "c"),
+            ]
+            .as_slice(),
+            &[
+                ("a.txt", "text/plain", "a"),
+                ("big.txt", "text/plain", too_large.as_str()),
+            ],
+        ] {
+            let req = upload_request(todo_id, &token, multipart(files)).to_request();
+            let res = actix_test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        assert_eq!(Attachment::find().count(&state.db).await.unwrap(), 0);
+        assert_eq!(blobs.blob_count(), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_download_is_never_rendered_inline() {
+        let (state, todo_id, token) = state().await;
+        let app = app!(state);
+
+        let body = multipart(&[
+            ("page.html", "text/html", "<script>alert(1)</script>"),
+            ("odd.bin", "not a mime type", "x"),
+        ]);
+        let req = upload_request(todo_id, &token, body).to_request();
+        let body: Value = actix_test::call_and_read_body_json(&app, req).await;
+
+        for (attachment, content_type) in body["attachments"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .zip(["text/html", "application/octet-stream"])
+        {
+            let req = actix_test::TestRequest::get()
+                .uri(&format!("/api/attachments/{}", attachment["id"]))
+                .insert_header((header::AUTHORIZATION, format!("Bearer {token}")))
+                .to_request();
+            let res = actix_test::call_service(&app, req).await;
+
+            let headers = res.headers();
+            assert_eq!(headers.get(header::CONTENT_TYPE).unwrap(), content_type);
+            assert_eq!(
+                headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(),
+                "nosniff"
+            );
+            assert!(headers
+                .get(header::CONTENT_DISPOSITION)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("attachment"));
+        }
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("report.pdf"), Some("report.pdf".to_string()));
+        assert_eq!(
+            sanitize_filename("../../etc/passwd"),
+            Some("passwd".to_string())
+        );
+        assert_eq!(
+            sanitize_filename("C:\\Users\\me\\notes.txt"),
+            Some("notes.txt".to_string())
+        );
+        assert_eq!(sanitize_filename("a\nb.txt"), Some("ab.txt".to_string()));
+        assert_eq!(sanitize_filename("dir/"), None);
+        assert_eq!(sanitize_filename(".."), None);
+    }
+}
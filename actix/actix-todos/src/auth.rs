@@ -1,8 +1,11 @@
-use crate::{entity::prelude::User, entity::user::ActiveModel, entity::user::Column::*, AppState};
+use crate::{
+    entity::prelude::User, entity::timestamp, entity::user::ActiveModel, entity::user::Column::*,
+    organizations, users, AppState,
+};
 use actix_web::{dev::ServiceRequest, *};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use bcrypt::{hash, verify, DEFAULT_COST};
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use bcrypt::{hash, verify};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use sea_orm::*;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -12,6 +15,8 @@ use std::time::{SystemTime, UNIX_EPOCH};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: u32,
+    /// The organisation the token is scoped to, every todo query filters on it.
+    pub org: i32,
     exp: usize,
 }
 
@@ -27,23 +32,33 @@ pub async fn register_user(
     state: web::Data<AppState>,
     input: web::Json<RegisterForm>,
 ) -> impl Responder {
-    let hashed_password = hash(input.password.clone(), DEFAULT_COST).unwrap();
+    let hashed_password = hash_password(&input.password).unwrap();
+    let verification_token = users::verification_token();
 
     let user = ActiveModel {
         id: NotSet,
         name: Set(input.name.clone()),
         email: Set(input.email.clone()),
         password: Set(hashed_password),
+        email_verification_token: Set(Some(verification_token.clone())),
+        email_verification_sent_at: Set(Some(timestamp())),
         ..Default::default()
     };
 
-    let user = user.save(&state.db).await.unwrap();
+    let txn = state.db.begin().await.unwrap();
+    let user = user.insert(&txn).await.unwrap();
+    organizations::create_organization(&txn, &user.name, user.id)
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+
+    users::send_verification_email(&state, &user.email, &verification_token).await;
 
     HttpResponse::Ok().body(
         json!({
             "user": {
-                "id": user.id.unwrap(),
-                "name": user.name.unwrap(),
+                "id": user.id,
+                "name": user.name,
             }
         })
         .to_string(),
@@ -54,6 +69,7 @@ pub async fn register_user(
 pub struct LoginRequest {
     email: String,
     password: String,
+    organization_id: Option<i32>,
 }
 
 #[post("/login")]
@@ -67,7 +83,24 @@ pub async fn login(state: web::Data<AppState>, input: web::Json<LoginRequest>) -
     let correct = verify(input.password.clone(), &user.password).unwrap_or(false);
 
     if correct {
-        let token = encode_jwt(user.id as u32).unwrap();
+        let organization = match input.organization_id {
+            Some(id) => organizations::find_membership(&state.db, user.id, id)
+                .await
+                .unwrap()
+                .map(|membership| membership.organization_id),
+            None => organizations::default_organization(&state.db, user.id)
+                .await
+                .unwrap(),
+        };
+        let organization = match organization {
+            Some(organization) => organization,
+            None => {
+                return HttpResponse::Forbidden()
+                    .body(json!({ "error": "not a member of any organization" }).to_string())
+            }
+        };
+
+        let token = encode_jwt(user.id as u32, organization).unwrap();
 
         HttpResponse::Ok().body(json!({ "token": token }).to_string())
     } else {
@@ -80,26 +113,42 @@ pub async fn login(state: web::Data<AppState>, input: web::Json<LoginRequest>) -
     }
 }
 
+/// The lowest cost bcrypt accepts keeps the tests fast.
+#[cfg(test)]
+const BCRYPT_COST: u32 = 4;
+#[cfg(not(test))]
+const BCRYPT_COST: u32 = bcrypt::DEFAULT_COST;
+
+pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
+    hash(password, BCRYPT_COST)
+}
+
+/// Rejects invalid tokens, and tokens for an organisation the user has since
+/// been removed from.
 pub async fn verify_jwt(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let claims = match decode_jwt(credentials.token()) {
+        Ok(claims) => claims,
+        Err(_) => return Err((actix_web::error::ErrorForbidden(""), req)),
+    };
 
-    match decode_jwt(credentials.token()) {
-        Ok(decoded) => {
-            Ok(req)
-        },
-        Err(_) => {
-            Err((actix_web::error::ErrorForbidden(""), req))
-        },
+    let state = req.app_data::<web::Data<AppState>>().unwrap();
+    match organizations::find_membership(&state.db, claims.sub as i32, claims.org).await {
+        Ok(Some(_)) => Ok(req),
+        Ok(None) => Err((actix_web::error::ErrorForbidden(""), req)),
+        Err(e) => Err((actix_web::error::ErrorInternalServerError(e), req)),
     }
-
-
 }
 
-pub fn encode_jwt(user_id: u32) -> std::result::Result<String, jsonwebtoken::errors::Error> {
+pub fn encode_jwt(
+    user_id: u32,
+    organization_id: i32,
+) -> std::result::Result<String, jsonwebtoken::errors::Error> {
     let claims = Claims {
         sub: user_id,
+        org: organization_id,
         exp: (SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("Time is acting wibbily wobbaly")
@@ -124,9 +173,49 @@ pub fn decode_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
     Ok(token.claims)
 }
 
-
 pub fn id_from_extractor(extractor: BearerAuth) -> u32 {
+    claims_from_extractor(extractor).sub
+}
+
+pub fn claims_from_extractor(extractor: BearerAuth) -> Claims {
     let token = extractor.token();
 
-    decode_jwt(token).unwrap().sub
-}
\ No newline at end of file
+    decode_jwt(token).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::register_user;
+    use crate::{
+        test_util::{self, RecordingMailer},
+        users::verify_email,
+    };
+    use actix_web::{test, web, App};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    #[actix_web::test]
+    async fn test_register_mails_a_verification_link() {
+        let mailer = Arc::new(RecordingMailer::default());
+        let state = test_util::state_with_mailer(mailer.clone()).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .service(register_user)
+                .service(verify_email),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/register")
+            .set_json(json!({ "name": "a", "email": "a@example.com", "password": "secret" }))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let link = mailer.last_link("a@example.com").unwrap();
+        let req = test::TestRequest::get().uri(&link).to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["user"]["email"], "a@example.com");
+        assert_eq!(body["user"]["email_verified"], true);
+    }
+}
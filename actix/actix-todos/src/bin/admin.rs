@@ -0,0 +1,252 @@
+use std::error::Error;
+
+use actix_todos::{
+    attachments,
+    auth::hash_password,
+    blobs::LocalBlobStore,
+    config::Config,
+    connect_to_db,
+    entity::prelude::{Todo, User},
+    entity::{todo, user},
+    organizations,
+};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+    TransactionTrait,
+};
+
+type MyResult<T> = Result<T, Box<dyn Error>>;
+
+const SEED_PASSWORD: &str = "password";
+
+fn get_args() -> ArgMatches<'static> {
+    let email = || {
+        Arg::with_name("email")
+            .long("email")
+            .value_name("EMAIL")
+            .help("email of the user")
+            .takes_value(true)
+    };
+
+    App::new("admin")
+        .version("0.1.0")
+        .about("actix-todos user and data management")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("create-user")
+                .about("create a new user")
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("display name")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(email().required(true))
+                .arg(
+                    Arg::with_name("password")
+                        .long("password")
+                        .value_name("PASSWORD")
+                        .help("plain text password, hashed before storing")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reset-password")
+                .about("set a new password for a user")
+                .arg(email().required(true))
+                .arg(
+                    Arg::with_name("password")
+                        .long("password")
+                        .value_name("PASSWORD")
+                        .help("new plain text password")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list-todos")
+                .about("list todos, optionally for a single user")
+                .arg(email()),
+        )
+        .subcommand(
+            SubCommand::with_name("purge-todos")
+                .about("delete todos, optionally for a single user")
+                .arg(email())
+                .arg(
+                    Arg::with_name("completed")
+                        .long("completed")
+                        .help("only delete completed todos")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("seed")
+                .about("insert demo users with todos")
+                .arg(
+                    Arg::with_name("users")
+                        .long("users")
+                        .value_name("USERS")
+                        .help("number of users to create")
+                        .default_value("3"),
+                )
+                .arg(
+                    Arg::with_name("todos")
+                        .long("todos")
+                        .value_name("TODOS")
+                        .help("number of todos per user")
+                        .default_value("5"),
+                ),
+        )
+        .get_matches()
+}
+
+#[actix_web::main]
+async fn main() {
+    if let Err(e) = run(get_args()).await {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+async fn run(matches: ArgMatches<'static>) -> MyResult<()> {
+    let db = connect_to_db().await?;
+
+    match matches.subcommand() {
+        ("create-user", Some(args)) => {
+            let (user, _) = create_user(
+                &db,
+                args.value_of("name").unwrap(),
+                args.value_of("email").unwrap(),
+                args.value_of("password").unwrap(),
+            )
+            .await?;
+            println!("created user {} <{}> with id {}", user.name, user.email, user.id);
+        }
+        ("reset-password", Some(args)) => {
+            let email = args.value_of("email").unwrap();
+            let mut user: user::ActiveModel = find_user(&db, email).await?.into();
+            user.password = Set(hash_password(args.value_of("password").unwrap())?);
+            user.update(&db).await?;
+            println!("password reset for {email}");
+        }
+        ("list-todos", Some(args)) => {
+            let mut query = Todo::find().order_by_asc(todo::Column::Id);
+            if let Some(email) = args.value_of("email") {
+                let user = find_user(&db, email).await?;
+                query = query.filter(todo::Column::UserId.eq(user.id));
+            }
+
+            for todo in query.all(&db).await? {
+                let status = if todo.status { "x" } else { " " };
+                println!(
+                    "{:>5} [{status}] {} (org {}, user {})",
+                    todo.id, todo.name, todo.organization_id, todo.user_id
+                );
+            }
+        }
+        ("purge-todos", Some(args)) => {
+            let mut query = Todo::find();
+            if let Some(email) = args.value_of("email") {
+                let user = find_user(&db, email).await?;
+                query = query.filter(todo::Column::UserId.eq(user.id));
+            }
+            if args.is_present("completed") {
+                query = query.filter(todo::Column::Status.eq(true));
+            }
+            let ids: Vec<i32> = query.all(&db).await?.into_iter().map(|todo| todo.id).collect();
+
+            let txn = db.begin().await?;
+            let keys = attachments::delete_for_todos(&txn, ids.clone()).await?;
+            let result = Todo::delete_many()
+                .filter(todo::Column::Id.is_in(ids))
+                .exec(&txn)
+                .await?;
+            txn.commit().await?;
+
+            let blobs = LocalBlobStore::new(Config::from_env()?.attachments_dir)?;
+            attachments::remove_blobs(&blobs, &keys).await;
+            println!(
+                "deleted {} todos and {} attachments",
+                result.rows_affected,
+                keys.len()
+            );
+        }
+        ("seed", Some(args)) => {
+            let users: usize = args.value_of("users").unwrap().parse()?;
+            let todos: usize = args.value_of("todos").unwrap().parse()?;
+            seed(&db, users, todos).await?;
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+async fn find_user(db: &DatabaseConnection, email: &str) -> MyResult<user::Model> {
+    User::find()
+        .filter(user::Column::Email.eq(email))
+        .one(db)
+        .await?
+        .ok_or_else(|| format!("no user with email {email}").into())
+}
+
+/// Creates the user together with their personal organisation, like
+/// registering through the API does.
+async fn create_user(
+    db: &DatabaseConnection,
+    name: &str,
+    email: &str,
+    password: &str,
+) -> MyResult<(user::Model, i32)> {
+    let txn = db.begin().await?;
+    let user = user::ActiveModel {
+        name: Set(name.to_string()),
+        email: Set(email.to_string()),
+        password: Set(hash_password(password)?),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    let organization = organizations::create_organization(&txn, name, user.id).await?;
+    txn.commit().await?;
+
+    Ok((user, organization.id))
+}
+
+async fn seed(db: &DatabaseConnection, users: usize, todos: usize) -> MyResult<()> {
+    for i in 1..=users {
+        let email = format!("demo{i}@example.com");
+        if User::find()
+            .filter(user::Column::Email.eq(email.as_str()))
+            .one(db)
+            .await?
+            .is_some()
+        {
+            println!("skipping {email}, already exists");
+            continue;
+        }
+
+        let (user, organization_id) =
+            create_user(db, &format!("Demo User {i}"), &email, SEED_PASSWORD).await?;
+
+        for j in 1..=todos {
+            todo::ActiveModel {
+                name: Set(format!("Demo todo {j}")),
+                status: Set(j % 2 == 0),
+                user_id: Set(user.id),
+                organization_id: Set(organization_id),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?;
+        }
+
+        println!("seeded {email} with {todos} todos (password: {SEED_PASSWORD})");
+    }
+
+    Ok(())
+}
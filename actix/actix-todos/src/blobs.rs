@@ -0,0 +1,108 @@
+use std::{io, path::PathBuf};
+
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, Rng};
+
+/// Storage for attachment contents. Blobs are addressed by keys handed out by
+/// [`new_key`], so an object store can be dropped in for [`LocalBlobStore`].
+#[async_trait]
+pub trait BlobStore: Send + Sync + std::fmt::Debug {
+    async fn put(&self, key: &str, data: &[u8]) -> io::Result<()>;
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+    /// Deleting a key that does not exist is not an error.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// Random key for a new blob. It never contains anything taken from the
+/// upload, so it is safe to use as a file name.
+pub fn new_key() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Stores each blob as a file named after its key in `root`.
+#[derive(Debug)]
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+
+        Ok(LocalBlobStore { root })
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid blob key {key:?}"),
+            ));
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        tokio::fs::write(self.path(key)?, data).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        tokio::fs::read(self.path(key)?).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{new_key, BlobStore, LocalBlobStore};
+    use std::io;
+
+    #[actix_web::test]
+    async fn test_local_blob_store_round_trip() {
+        let root = std::env::temp_dir().join(format!("actix-todos-blobs-{}", new_key()));
+        let store = LocalBlobStore::new(&root).unwrap();
+        let key = new_key();
+
+        store.put(&key, b"hello").await.unwrap();
+        assert_eq!(store.get(&key).await.unwrap(), b"hello");
+
+        store.delete(&key).await.unwrap();
+        store.delete(&key).await.unwrap();
+        assert_eq!(
+            store.get(&key).await.unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_local_blob_store_rejects_paths() {
+        let root = std::env::temp_dir().join(format!("actix-todos-blobs-{}", new_key()));
+        let store = LocalBlobStore::new(&root).unwrap();
+
+        for key in ["../secret", "a/b", ""] {
+            assert_eq!(
+                store.put(key, b"x").await.unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, Rng};
+
+/// Storage behind [`TodoCache`]. Values are opaque strings with a TTL so a
+/// Redis-compatible store (`GET`, `SET EX`, `DEL`) can be dropped in for
+/// [`MemoryCache`].
+#[async_trait]
+pub trait CacheBackend: Send + Sync {
+    async fn get(&self, key: &str) -> Option<String>;
+    async fn set(&self, key: &str, value: String, ttl: Duration);
+    async fn delete(&self, key: &str);
+}
+
+/// In-process backend. Expired entries are dropped when they are read, and
+/// swept whenever the map grows past `max_entries`. If every entry is still
+/// live, the one closest to expiring is evicted.
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, (Instant, String)>>,
+    max_entries: usize,
+}
+
+impl MemoryCache {
+    pub fn new(max_entries: usize) -> Self {
+        MemoryCache {
+            entries: Mutex::new(HashMap::new()),
+            max_entries,
+        }
+    }
+}
+
+#[async_trait]
+impl CacheBackend for MemoryCache {
+    async fn get(&self, key: &str) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((expires, value)) if *expires > Instant::now() => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(key) {
+            let now = Instant::now();
+            entries.retain(|_, (expires, _)| *expires > now);
+
+            if entries.len() >= self.max_entries {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, (expires, _))| *expires)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(key.to_string(), (Instant::now() + ttl, value));
+    }
+
+    async fn delete(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+/// Per-organisation cache of the serialized `GET /api/todos` response. Every
+/// code path that changes an organisation's todos must call
+/// [`TodoCache::invalidate`].
+///
+/// Each organisation has a random version, dropped on invalidation, and a
+/// body is only served while the version it was stored under is current. A
+/// body read from the database before a concurrent invalidation is therefore
+/// never served, even if it is stored after it.
+#[derive(Clone)]
+pub struct TodoCache {
+    backend: Arc<dyn CacheBackend>,
+    ttl: Duration,
+}
+
+/// The version of an organisation's entry, taken with [`TodoCache::version`]
+/// before reading the todos and handed back to [`TodoCache::put`].
+#[derive(Debug)]
+pub struct CacheVersion(String);
+
+impl TodoCache {
+    pub fn new(backend: Arc<dyn CacheBackend>, ttl: Duration) -> Self {
+        TodoCache { backend, ttl }
+    }
+
+    pub async fn get(&self, organization_id: i32) -> Option<String> {
+        let version = self.backend.get(&version_key(organization_id)).await?;
+        let entry = self.backend.get(&key(organization_id)).await?;
+
+        match entry.split_once(':') {
+            Some((entry_version, body)) if entry_version == version => Some(body.to_string()),
+            _ => None,
+        }
+    }
+
+    /// The current version, or a new one when there is none.
+    pub async fn version(&self, organization_id: i32) -> CacheVersion {
+        let key = version_key(organization_id);
+        if let Some(version) = self.backend.get(&key).await {
+            return CacheVersion(version);
+        }
+
+        let version: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        self.backend.set(&key, version.clone(), self.ttl).await;
+
+        CacheVersion(version)
+    }
+
+    pub async fn put(&self, organization_id: i32, version: &CacheVersion, body: String) {
+        let entry = format!("{}:{body}", version.0);
+        self.backend
+            .set(&key(organization_id), entry, self.ttl)
+            .await
+    }
+
+    pub async fn invalidate(&self, organization_id: i32) {
+        self.backend.delete(&version_key(organization_id)).await;
+        self.backend.delete(&key(organization_id)).await
+    }
+}
+
+impl std::fmt::Debug for TodoCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TodoCache").field("ttl", &self.ttl).finish()
+    }
+}
+
+impl Default for TodoCache {
+    fn default() -> Self {
+        TodoCache::new(Arc::new(MemoryCache::new(10_000)), Duration::from_secs(30))
+    }
+}
+
+fn key(organization_id: i32) -> String {
+    format!("todos:org:{organization_id}")
+}
+
+fn version_key(organization_id: i32) -> String {
+    format!("todos:org:{organization_id}:version")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheBackend, MemoryCache, TodoCache};
+    use std::{sync::Arc, time::Duration};
+
+    #[actix_web::test]
+    async fn test_memory_cache_expires_entries() {
+        let cache = MemoryCache::new(10);
+
+        cache
+            .set("a", "1".to_string(), Duration::from_secs(60))
+            .await;
+        cache.set("b", "2".to_string(), Duration::ZERO).await;
+
+        assert_eq!(cache.get("a").await, Some("1".to_string()));
+        assert_eq!(cache.get("b").await, None);
+    }
+
+    #[actix_web::test]
+    async fn test_memory_cache_sweeps_when_full() {
+        let cache = MemoryCache::new(2);
+
+        cache.set("a", "1".to_string(), Duration::ZERO).await;
+        cache.set("b", "2".to_string(), Duration::ZERO).await;
+        cache
+            .set("c", "3".to_string(), Duration::from_secs(60))
+            .await;
+
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_memory_cache_evicts_when_full_of_live_entries() {
+        let cache = MemoryCache::new(2);
+
+        cache
+            .set("a", "1".to_string(), Duration::from_secs(30))
+            .await;
+        cache
+            .set("b", "2".to_string(), Duration::from_secs(60))
+            .await;
+        cache
+            .set("c", "3".to_string(), Duration::from_secs(60))
+            .await;
+
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+        assert_eq!(cache.get("a").await, None);
+        assert_eq!(cache.get("c").await, Some("3".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn test_todo_cache_is_per_organization() {
+        let cache = TodoCache::new(Arc::new(MemoryCache::new(10)), Duration::from_secs(60));
+
+        for organization_id in [1, 2] {
+            let version = cache.version(organization_id).await;
+            cache
+                .put(organization_id, &version, format!("[{organization_id}]"))
+                .await;
+        }
+        cache.invalidate(1).await;
+
+        assert_eq!(cache.get(1).await, None);
+        assert_eq!(cache.get(2).await, Some("[2]".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn test_todo_cache_drops_puts_read_before_an_invalidation() {
+        let cache = TodoCache::new(Arc::new(MemoryCache::new(10)), Duration::from_secs(60));
+
+        let version = cache.version(1).await;
+        // A write lands while the todos are being read.
+        cache.invalidate(1).await;
+        cache.put(1, &version, "stale".to_string()).await;
+        assert_eq!(cache.get(1).await, None);
+
+        let version = cache.version(1).await;
+        cache.put(1, &version, "fresh".to_string()).await;
+        assert_eq!(cache.get(1).await, Some("fresh".to_string()));
+    }
+}
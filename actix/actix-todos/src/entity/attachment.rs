@@ -0,0 +1,46 @@
+use sea_orm::{entity::prelude::*, Set};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "attachment")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub todo_id: i32,
+    pub user_id: i32,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    #[sea_orm(unique)]
+    pub storage_key: String,
+    pub created_at: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::todo::Entity",
+        from = "Column::TodoId",
+        to = "super::todo::Column::Id"
+    )]
+    Todo,
+}
+
+impl Related<super::todo::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Todo.def()
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert && self.created_at.is_not_set() {
+            self.created_at = Set(Some(super::timestamp()));
+        }
+
+        Ok(self)
+    }
+}
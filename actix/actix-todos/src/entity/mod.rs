@@ -2,5 +2,19 @@
 
 pub mod prelude;
 
+pub mod attachment;
+pub mod organization;
+pub mod organization_member;
 pub mod todo;
 pub mod user;
+pub mod user_identity;
+pub mod webhook;
+pub mod webhook_delivery;
+
+/// The format SQLite's `CURRENT_TIMESTAMP` uses.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Current UTC time in [`TIMESTAMP_FORMAT`].
+pub fn timestamp() -> String {
+    chrono::Utc::now().format(TIMESTAMP_FORMAT).to_string()
+}
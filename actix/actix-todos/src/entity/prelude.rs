@@ -1,4 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.2
 
+pub use super::attachment::Entity as Attachment;
+pub use super::organization::Entity as Organization;
+pub use super::organization_member::Entity as OrganizationMember;
 pub use super::todo::Entity as Todo;
 pub use super::user::Entity as User;
+pub use super::user_identity::Entity as UserIdentity;
+pub use super::webhook::Entity as Webhook;
+pub use super::webhook_delivery::Entity as WebhookDelivery;
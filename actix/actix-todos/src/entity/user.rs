@@ -1,6 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.2
 
-use sea_orm::entity::prelude::*;
+use sea_orm::{entity::prelude::*, Set};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "user")]
@@ -12,9 +12,53 @@ pub struct Model {
     pub password: String,
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
+    pub email_verified_at: Option<String>,
+    pub pending_email: Option<String>,
+    pub email_verification_token: Option<String>,
+    pub email_verification_sent_at: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::organization_member::Entity")]
+    OrganizationMember,
+    #[sea_orm(has_many = "super::todo::Entity")]
+    Todo,
+    #[sea_orm(has_many = "super::user_identity::Entity")]
+    UserIdentity,
+}
+
+impl Related<super::organization_member::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrganizationMember.def()
+    }
+}
+
+impl Related<super::todo::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Todo.def()
+    }
+}
 
-impl ActiveModelBehavior for ActiveModel {}
+impl Related<super::user_identity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserIdentity.def()
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = super::timestamp();
+
+        if insert && self.created_at.is_not_set() {
+            self.created_at = Set(Some(now.clone()));
+        }
+        self.updated_at = Set(Some(now));
+
+        Ok(self)
+    }
+}
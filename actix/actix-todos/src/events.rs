@@ -0,0 +1,47 @@
+use tokio::sync::broadcast;
+
+use crate::entity::todo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TodoEventKind {
+    Created,
+    Completed,
+    Deleted,
+}
+
+#[derive(Debug, Clone)]
+pub struct TodoEvent {
+    pub kind: TodoEventKind,
+    pub todo: todo::Model,
+}
+
+/// In-process fan-out of todo changes. Subscribers that fall more than
+/// `CAPACITY` events behind skip the events they missed.
+#[derive(Debug, Clone)]
+pub struct TodoEvents {
+    sender: broadcast::Sender<TodoEvent>,
+}
+
+const CAPACITY: usize = 256;
+
+impl TodoEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        TodoEvents { sender }
+    }
+
+    pub fn publish(&self, kind: TodoEventKind, todo: todo::Model) {
+        // An error only means nobody is listening right now.
+        let _ = self.sender.send(TodoEvent { kind, todo });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TodoEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for TodoEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,273 @@
+mod schema;
+
+use actix_web::{
+    error::ErrorForbidden,
+    guard,
+    http::header,
+    web::{self, Data, Payload, ServiceConfig},
+    HttpRequest, HttpResponse, Result,
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use async_graphql::Schema;
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
+
+use sea_orm::DatabaseConnection;
+
+use crate::{auth::decode_jwt, organizations::find_membership, AppState};
+
+pub use schema::{CurrentUser, MutationRoot, QueryRoot, SubscriptionRoot};
+
+pub type TodoSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+const ENDPOINT: &str = "/api/graphql";
+
+/// GraphiQL is an inline script that pulls React and its own bundle from
+/// unpkg, the global policy from `middleware::security_headers` blocks all of
+/// that.
+const GRAPHIQL_CSP: &str = "default-src 'none'; \
+    script-src 'unsafe-inline' https://unpkg.com; \
+    style-src 'unsafe-inline' https://unpkg.com; \
+    img-src https://graphql.org data:; \
+    font-src https://unpkg.com data:; \
+    connect-src 'self'; \
+    frame-ancestors 'none'";
+
+pub fn build_schema(state: &AppState) -> TodoSchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(state.db.clone())
+        .data(state.events.clone())
+        .data(state.cache.clone())
+        .data(state.blobs.clone())
+        .finish()
+}
+
+/// Registers `/api/graphql`. It has to be registered before the `/api` scope:
+/// websocket clients cannot send an `Authorization` header, so this resource
+/// authenticates its requests itself instead of going through the scope's
+/// bearer middleware.
+pub fn config(cfg: &mut ServiceConfig) {
+    let mut resource = web::resource(ENDPOINT)
+        .route(web::post().to(graphql))
+        .route(
+            web::get()
+                .guard(guard::Header("upgrade", "websocket"))
+                .to(graphql_ws),
+        );
+
+    if cfg!(debug_assertions) {
+        resource = resource.route(web::get().to(graphiql));
+    }
+
+    cfg.service(resource);
+}
+
+async fn graphql(
+    schema: Data<TodoSchema>,
+    state: Data<AppState>,
+    auth: BearerAuth,
+    request: GraphQLRequest,
+) -> Result<GraphQLResponse> {
+    let user = current_user(&state.db, auth.token())
+        .await
+        .ok_or_else(|| ErrorForbidden(""))?;
+
+    Ok(schema.execute(request.into_inner().data(user)).await.into())
+}
+
+/// Subscriptions over graphql-ws. The token is taken from the upgrade request's
+/// `Authorization` header or, for browsers, from the `Authorization` or
+/// `token` field of the `connection_init` payload.
+async fn graphql_ws(
+    schema: Data<TodoSchema>,
+    state: Data<AppState>,
+    req: HttpRequest,
+    payload: Payload,
+) -> Result<HttpResponse> {
+    let header_user = match req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(value) => current_user(&state.db, strip_bearer(value)).await,
+        None => None,
+    };
+    let db = state.db.clone();
+
+    GraphQLSubscription::new(Schema::clone(&*schema))
+        .on_connection_init(move |value| async move {
+            let token = value
+                .get("Authorization")
+                .or_else(|| value.get("token"))
+                .and_then(|token| token.as_str());
+            let user = match (header_user, token) {
+                (Some(user), _) => Some(user),
+                (None, Some(token)) => current_user(&db, strip_bearer(token)).await,
+                (None, None) => None,
+            };
+
+            let mut data = async_graphql::Data::default();
+            data.insert(user.ok_or("missing or invalid token")?);
+            Ok(data)
+        })
+        .start(&req, payload)
+}
+
+async fn graphiql() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CONTENT_SECURITY_POLICY, GRAPHIQL_CSP))
+        .body(
+            async_graphql::http::GraphiQLSource::build()
+                .endpoint(ENDPOINT)
+                .subscription_endpoint(ENDPOINT)
+                .finish(),
+        )
+}
+
+/// Same checks as `auth::verify_jwt`: the token must be valid and the user
+/// still a member of the organisation it is scoped to.
+async fn current_user(db: &DatabaseConnection, token: &str) -> Option<CurrentUser> {
+    let claims = decode_jwt(token).ok()?;
+    find_membership(db, claims.sub as i32, claims.org)
+        .await
+        .ok()??;
+
+    Some(CurrentUser {
+        id: claims.sub as i32,
+        organization_id: claims.org,
+    })
+}
+
+fn strip_bearer(value: &str) -> &str {
+    value.strip_prefix("Bearer ").unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_schema, config, CurrentUser};
+    use crate::{entity::user, middleware::security_headers, organizations, test_util, AppState};
+    use actix_web::{http::header, test, App};
+    use async_graphql::Request;
+    use sea_orm::{ActiveModelTrait, Set};
+    use serde_json::json;
+
+    async fn state() -> AppState {
+        let state = test_util::state().await;
+
+        for email in ["a@example.com", "b@example.com"] {
+            let user = user::ActiveModel {
+                name: Set(email.to_string()),
+                email: Set(email.to_string()),
+                password: Set(String::new()),
+                ..Default::default()
+            }
+            .insert(&state.db)
+            .await
+            .unwrap();
+            organizations::create_organization(&state.db, email, user.id)
+                .await
+                .unwrap();
+        }
+
+        state
+    }
+
+    /// Each test user is the only member of the organisation with their id.
+    fn user(id: i32) -> CurrentUser {
+        CurrentUser {
+            id,
+            organization_id: id,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_todos_are_scoped_to_organization() {
+        let schema = build_schema(&state().await);
+
+        let res = schema
+            .execute(
+                Request::new(r#"mutation { createTodo(name: "milk") { id name status } }"#)
+                    .data(user(1)),
+            )
+            .await;
+        assert!(res.errors.is_empty());
+        assert_eq!(
+            res.data.into_json().unwrap(),
+            json!({ "createTodo": { "id": 1, "name": "milk", "status": false } })
+        );
+
+        let res = schema
+            .execute(Request::new("{ me { email todos { name } } }").data(user(1)))
+            .await;
+        assert_eq!(
+            res.data.into_json().unwrap(),
+            json!({ "me": { "email": "a@example.com", "todos": [{ "name": "milk" }] } })
+        );
+
+        let res = schema
+            .execute(Request::new("{ todos { id } }").data(user(2)))
+            .await;
+        assert_eq!(res.data.into_json().unwrap(), json!({ "todos": [] }));
+
+        let res = schema
+            .execute(Request::new("mutation { completeTodo(id: 1) { id } }").data(user(2)))
+            .await;
+        assert_eq!(res.errors[0].message, "todo not found");
+    }
+
+    #[actix_web::test]
+    async fn test_organization_members_share_todos() {
+        let schema = build_schema(&state().await);
+
+        let res = schema
+            .execute(Request::new(r#"mutation { createTodo(name: "milk") { id } }"#).data(user(1)))
+            .await;
+        assert!(res.errors.is_empty());
+
+        let member = CurrentUser {
+            id: 2,
+            organization_id: 1,
+        };
+        let res = schema
+            .execute(Request::new("mutation { completeTodo(id: 1) { status } }").data(member))
+            .await;
+        assert_eq!(
+            res.data.into_json().unwrap(),
+            json!({ "completeTodo": { "status": true } })
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_requires_current_user() {
+        let schema = build_schema(&state().await);
+
+        let res = schema.execute("{ todos { id } }").await;
+        assert!(!res.errors.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_graphiql_csp_allows_its_scripts() {
+        let app =
+            test::init_service(App::new().wrap(security_headers(false)).configure(config)).await;
+
+        let req = test::TestRequest::get().uri("/api/graphql").to_request();
+        let res = test::call_service(&app, req).await;
+
+        assert!(res.status().is_success());
+        let csp = res
+            .headers()
+            .get(header::CONTENT_SECURITY_POLICY)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(csp.contains("script-src 'unsafe-inline' https://unpkg.com"));
+        assert!(csp.contains("style-src 'unsafe-inline' https://unpkg.com"));
+        assert!(csp.contains("connect-src 'self'"));
+        assert_eq!(
+            res.headers()
+                .get_all(header::CONTENT_SECURITY_POLICY)
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use async_graphql::{Context, Enum, Object, Result, SimpleObject, Subscription};
+use futures_util::{future, Stream, StreamExt};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+use std::sync::Arc;
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::{
+    attachments,
+    blobs::BlobStore,
+    cache::TodoCache,
+    entity::prelude,
+    entity::{todo, user},
+    events::{self, TodoEvents},
+};
+
+/// The authenticated user a request is executed for, and the organisation
+/// their token is scoped to.
+#[derive(Debug, Clone, Copy)]
+pub struct CurrentUser {
+    pub id: i32,
+    pub organization_id: i32,
+}
+
+fn current_user(ctx: &Context<'_>) -> Result<CurrentUser> {
+    Ok(*ctx.data::<CurrentUser>()?)
+}
+
+async fn find_todo(db: &DatabaseConnection, organization_id: i32, id: i32) -> Result<todo::Model> {
+    prelude::Todo::find_by_id(id)
+        .filter(todo::Column::OrganizationId.eq(organization_id))
+        .one(db)
+        .await?
+        .ok_or_else(|| "todo not found".into())
+}
+
+pub struct User(user::Model);
+
+#[Object]
+impl User {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn email(&self) -> &str {
+        &self.0.email
+    }
+
+    async fn email_verified(&self) -> bool {
+        self.0.email_verified_at.is_some()
+    }
+
+    async fn created_at(&self) -> Option<&str> {
+        self.0.created_at.as_deref()
+    }
+
+    async fn updated_at(&self) -> Option<&str> {
+        self.0.updated_at.as_deref()
+    }
+
+    /// Todos the user created in the active organisation.
+    async fn todos(&self, ctx: &Context<'_>) -> Result<Vec<Todo>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let todos = self
+            .0
+            .find_related(prelude::Todo)
+            .filter(todo::Column::OrganizationId.eq(current_user(ctx)?.organization_id))
+            .order_by_asc(todo::Column::Id)
+            .all(db)
+            .await?;
+
+        Ok(todos.into_iter().map(Todo::from).collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Todo {
+    id: i32,
+    name: String,
+    status: bool,
+}
+
+impl From<todo::Model> for Todo {
+    fn from(todo: todo::Model) -> Self {
+        Todo {
+            id: todo.id,
+            name: todo.name,
+            status: todo.status,
+        }
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum TodoEventKind {
+    Created,
+    Completed,
+    Deleted,
+}
+
+impl From<events::TodoEventKind> for TodoEventKind {
+    fn from(kind: events::TodoEventKind) -> Self {
+        match kind {
+            events::TodoEventKind::Created => TodoEventKind::Created,
+            events::TodoEventKind::Completed => TodoEventKind::Completed,
+            events::TodoEventKind::Deleted => TodoEventKind::Deleted,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct TodoEvent {
+    kind: TodoEventKind,
+    todo: Todo,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn me(&self, ctx: &Context<'_>) -> Result<User> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user = prelude::User::find_by_id(current_user(ctx)?.id)
+            .one(db)
+            .await?
+            .ok_or("user not found")?;
+
+        Ok(User(user))
+    }
+
+    async fn todos(&self, ctx: &Context<'_>) -> Result<Vec<Todo>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let todos = prelude::Todo::find()
+            .filter(todo::Column::OrganizationId.eq(current_user(ctx)?.organization_id))
+            .order_by_asc(todo::Column::Id)
+            .all(db)
+            .await?;
+
+        Ok(todos.into_iter().map(Todo::from).collect())
+    }
+
+    async fn todo(&self, ctx: &Context<'_>, id: i32) -> Result<Todo> {
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(find_todo(db, current_user(ctx)?.organization_id, id).await?.into())
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn create_todo(&self, ctx: &Context<'_>, name: String) -> Result<Todo> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user = current_user(ctx)?;
+        let todo = todo::ActiveModel {
+            name: Set(name),
+            user_id: Set(user.id),
+            organization_id: Set(user.organization_id),
+            status: Set(false),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+
+        ctx.data::<TodoCache>()?.invalidate(todo.organization_id).await;
+        ctx.data::<TodoEvents>()?
+            .publish(events::TodoEventKind::Created, todo.clone());
+
+        Ok(todo.into())
+    }
+
+    async fn complete_todo(&self, ctx: &Context<'_>, id: i32) -> Result<Todo> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let organization_id = current_user(ctx)?.organization_id;
+        let mut todo: todo::ActiveModel = find_todo(db, organization_id, id).await?.into();
+        todo.status = Set(true);
+        let todo = todo.update(db).await?;
+
+        ctx.data::<TodoCache>()?.invalidate(todo.organization_id).await;
+        ctx.data::<TodoEvents>()?
+            .publish(events::TodoEventKind::Completed, todo.clone());
+
+        Ok(todo.into())
+    }
+
+    async fn delete_todo(&self, ctx: &Context<'_>, id: i32) -> Result<bool> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let todo = find_todo(db, current_user(ctx)?.organization_id, id).await?;
+        let txn = db.begin().await?;
+        let keys = attachments::delete_for_todos(&txn, vec![todo.id]).await?;
+        todo.clone().delete(&txn).await?;
+        txn.commit().await?;
+        attachments::remove_blobs(ctx.data::<Arc<dyn BlobStore>>()?.as_ref(), &keys).await;
+
+        ctx.data::<TodoCache>()?.invalidate(todo.organization_id).await;
+        ctx.data::<TodoEvents>()?
+            .publish(events::TodoEventKind::Deleted, todo);
+
+        Ok(true)
+    }
+
+    async fn update_profile(&self, ctx: &Context<'_>, name: String) -> Result<User> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user = prelude::User::find_by_id(current_user(ctx)?.id)
+            .one(db)
+            .await?
+            .ok_or("user not found")?;
+
+        let mut user: user::ActiveModel = user.into();
+        user.name = Set(name);
+
+        Ok(User(user.update(db).await?))
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Todo changes in the active organisation, whether made over REST or
+    /// GraphQL.
+    async fn todo_events(&self, ctx: &Context<'_>) -> Result<impl Stream<Item = TodoEvent>> {
+        let organization_id = current_user(ctx)?.organization_id;
+        let receiver = ctx.data::<TodoEvents>()?.subscribe();
+
+        Ok(BroadcastStream::new(receiver).filter_map(move |event| {
+            future::ready(match event {
+                Ok(event) if event.todo.organization_id == organization_id => Some(TodoEvent {
+                    kind: event.kind.into(),
+                    todo: event.todo.into(),
+                }),
+                _ => None,
+            })
+        }))
+    }
+}
@@ -0,0 +1,51 @@
+pub mod attachments;
+pub mod auth;
+pub mod blobs;
+pub mod cache;
+pub mod config;
+pub mod entity;
+pub mod events;
+pub mod graphql;
+pub mod mailer;
+pub mod middleware;
+pub mod oidc;
+pub mod organizations;
+pub mod todos;
+pub mod users;
+pub mod webhooks;
+
+#[cfg(test)]
+mod test_util;
+
+use blobs::BlobStore;
+use cache::TodoCache;
+use events::TodoEvents;
+use mailer::Mailer;
+use sea_orm::{Database, DatabaseConnection, DbErr};
+use std::{env, sync::Arc};
+
+#[derive(Debug, Clone)]
+pub struct AppState {
+    pub db: DatabaseConnection,
+    pub events: TodoEvents,
+    pub cache: TodoCache,
+    pub blobs: Arc<dyn BlobStore>,
+    pub mailer: Arc<dyn Mailer>,
+    /// Where the server is reachable from outside, links in mails start with
+    /// it.
+    pub public_url: String,
+}
+
+/// Connects to `DATABASE_URL` when it is set, otherwise to `database.sqlite`
+/// in the current directory.
+pub async fn connect_to_db() -> Result<DatabaseConnection, DbErr> {
+    let url = match env::var("DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            let path = env::current_dir().map_err(|e| DbErr::Custom(e.to_string()))?;
+            format!("sqlite:{}/database.sqlite", path.display())
+        }
+    };
+
+    Database::connect(url).await
+}
@@ -0,0 +1,74 @@
+use std::io;
+
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+/// Outgoing mail. Messages can carry secrets such as verification links, so
+/// implementations must never write the body to a log.
+#[async_trait]
+pub trait Mailer: Send + Sync + std::fmt::Debug {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> io::Result<()>;
+}
+
+/// Sends plain text mail through an SMTP server.
+#[derive(Debug)]
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// `url` is an `smtp://` or `smtps://` URL with optional credentials,
+    /// `from` the sender, e.g. `Todos <todos@example.com>`.
+    pub fn new(url: &str, from: &str) -> io::Result<Self> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|e| invalid(format!("SMTP_URL: {e}")))?
+            .build();
+        let from = from
+            .parse()
+            .map_err(|e| invalid(format!("MAIL_FROM: {e}")))?;
+
+        Ok(SmtpMailer { transport, from })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> io::Result<()> {
+        let to: Mailbox = to.parse().map_err(|e| invalid(format!("{to}: {e}")))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.to_string())
+            .map_err(|e| invalid(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+}
+
+/// For `MAIL_LOG_ONLY`: records that a message would have been sent, without
+/// its body.
+#[derive(Debug, Default)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, to: &str, subject: &str, _body: &str) -> io::Result<()> {
+        log::info!("MAIL_LOG_ONLY is set, not sending {subject:?} to {to}");
+
+        Ok(())
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
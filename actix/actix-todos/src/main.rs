@@ -1,19 +1,20 @@
-pub mod auth;
-pub mod entity;
-pub mod middleware;
-pub mod todos;
-
+use actix_todos::{
+    attachments, auth,
+    blobs::LocalBlobStore,
+    cache::{MemoryCache, TodoCache},
+    config::{Config, MailConfig},
+    connect_to_db,
+    events::TodoEvents,
+    graphql,
+    mailer::{LogMailer, Mailer, SmtpMailer},
+    middleware,
+    oidc::{self, OidcClient},
+    organizations, todos, users, webhooks, AppState,
+};
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use actix_web_httpauth::middleware::HttpAuthentication;
-use migration::{Migrator, MigratorTrait};
-use sea_orm::{entity::*, Database, DatabaseConnection};
-use std::env;
-
-#[derive(Debug, Clone)]
-pub struct AppState {
-    db: DatabaseConnection,
-}
+use std::{sync::Arc, time::Duration};
 
 #[get("/")]
 async fn home() -> impl Responder {
@@ -23,45 +24,113 @@ async fn home() -> impl Responder {
 #[get("/protected")]
 async fn protected(auth: BearerAuth) -> impl Responder {
     let token = auth::decode_jwt(auth.token()).unwrap();
-    let user_id = token.sub;
+    let _user_id = token.sub;
 
     HttpResponse::Ok().body("welcome to the club")
 }
 
-async fn connect_to_db() -> std::io::Result<DatabaseConnection> {
-    let path = env::current_dir()?;
-
-    let db = Database::connect(format!("sqlite:{}/database.sqlite", path.display()))
-        .await
-        .unwrap();
-
-    Ok(db)
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // `RUST_LOG` overrides the level, e.g. `RUST_LOG=debug`.
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info,sqlx=warn"));
+
+    let config = Config::from_env()?;
+
+    let mailer: Arc<dyn Mailer> = match &config.mail {
+        Some(MailConfig::Smtp { url, from }) => Arc::new(SmtpMailer::new(url, from)?),
+        Some(MailConfig::LogOnly) => {
+            log::warn!("MAIL_LOG_ONLY is set, verification mails are not sent");
+            Arc::new(LogMailer)
+        }
+        None => {
+            return Err(std::io::Error::other(
+                "no mail transport, set SMTP_URL and MAIL_FROM, or MAIL_LOG_ONLY=true",
+            ))
+        }
+    };
     let state = AppState {
-        db: connect_to_db().await?,
+        db: connect_to_db().await.unwrap(),
+        events: TodoEvents::new(),
+        cache: TodoCache::new(
+            Arc::new(MemoryCache::new(10_000)),
+            Duration::from_secs(config.todo_cache_ttl),
+        ),
+        blobs: Arc::new(LocalBlobStore::new(&config.attachments_dir)?),
+        mailer,
+        public_url: config.public_url.clone(),
+    };
+    let schema = graphql::build_schema(&state);
+    webhooks::spawn_worker(state.db.clone(), &state.events);
+
+    let oidc_client = match config.oidc.clone() {
+        Some(oidc) => Some(
+            OidcClient::discover(oidc, Arc::new(MemoryCache::new(10_000)))
+                .await
+                .map_err(std::io::Error::other)?,
+        ),
+        None => None,
     };
 
-    HttpServer::new(move || {
+    let app_config = config.clone();
+    let mut server = HttpServer::new(move || {
         let auth = HttpAuthentication::bearer(auth::verify_jwt);
 
         App::new()
+            .wrap(middleware::cors(&app_config.cors))
+            .wrap(middleware::security_headers(app_config.tls.is_some()))
             .app_data(web::Data::new(state.clone()))
+            .app_data(web::Data::new(schema.clone()))
+            .app_data(web::Data::new(app_config.clone()))
             .service(home)
             .service(auth::register_user)
             .service(auth::login)
+            .service(users::verify_email)
+            .configure(|cfg| {
+                if let Some(client) = &oidc_client {
+                    oidc::config(cfg, client.clone());
+                }
+            })
+            .configure(graphql::config)
             .service(
                 web::scope("/api")
                     .wrap(auth)
                     .service(protected)
                     .service(todos::create_todo)
                     .service(todos::get_todos)
-                    .service(todos::complete_todo),
+                    .service(todos::complete_todo)
+                    .service(attachments::upload)
+                    .service(attachments::list)
+                    .service(attachments::download)
+                    .service(attachments::remove)
+                    .service(users::get_me)
+                    .service(users::update_me)
+                    .service(users::resend_verification)
+                    .service(users::change_password)
+                    .service(users::delete_me)
+                    .service(organizations::get_organizations)
+                    .service(organizations::create)
+                    .service(organizations::add_member)
+                    .service(organizations::remove_member)
+                    .service(organizations::switch)
+                    .service(webhooks::create)
+                    .service(webhooks::list)
+                    .service(webhooks::remove)
+                    .service(webhooks::deliveries),
             )
     })
-    .bind(("127.0.0.1", 8080))?
-    .run()
-    .await
+    .shutdown_timeout(config.shutdown_timeout);
+
+    if let Some(workers) = config.workers {
+        server = server.workers(workers);
+    }
+
+    let address = (config.host.as_str(), config.port);
+    server = match &config.tls {
+        Some(tls) => server.bind_rustls(address, tls.load()?)?,
+        None => server.bind(address)?,
+    };
+
+    // actix-web stops accepting on SIGINT/SIGTERM and waits up to
+    // `shutdown_timeout` for in-flight requests before exiting.
+    server.run().await
 }
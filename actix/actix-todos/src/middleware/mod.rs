@@ -0,0 +1,124 @@
+use actix_cors::Cors;
+use actix_web::{http::header, middleware::DefaultHeaders};
+
+use crate::config::CorsConfig;
+
+pub fn cors(config: &CorsConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(config.allowed_methods.clone())
+        .allowed_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::ACCEPT])
+        .max_age(config.max_age);
+
+    for origin in &config.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+
+    if config.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+
+    cors
+}
+
+/// Headers added to every response unless a handler already set them. HSTS is
+/// only sent when the server is behind TLS, browsers ignore it over plain HTTP.
+pub fn security_headers(hsts: bool) -> DefaultHeaders {
+    let headers = DefaultHeaders::new()
+        .add((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .add((header::X_FRAME_OPTIONS, "DENY"))
+        .add((header::REFERRER_POLICY, "no-referrer"))
+        .add((
+            header::CONTENT_SECURITY_POLICY,
+            "default-src 'none'; frame-ancestors 'none'",
+        ));
+
+    if hsts {
+        headers.add((
+            header::STRICT_TRANSPORT_SECURITY,
+            "max-age=31536000; includeSubDomains",
+        ))
+    } else {
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cors, security_headers};
+    use crate::config::CorsConfig;
+    use actix_web::{http::header, http::Method, test, web, App, HttpResponse};
+
+    fn cors_config() -> CorsConfig {
+        CorsConfig {
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            allowed_methods: vec![Method::GET, Method::POST],
+            allow_credentials: true,
+            max_age: 600,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_preflight_from_allowed_origin() {
+        let app = test::init_service(
+            App::new()
+                .wrap(cors(&cors_config()))
+                .wrap(security_headers(true))
+                .route("/api/todos", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/api/todos")
+            .insert_header((header::ORIGIN, "https://app.example.com"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+
+        assert!(res.status().is_success());
+        let headers = res.headers();
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://app.example.com"
+        );
+        assert_eq!(
+            headers
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+        assert!(headers.contains_key(header::STRICT_TRANSPORT_SECURITY));
+    }
+
+    #[actix_web::test]
+    async fn test_request_from_unknown_origin() {
+        let app = test::init_service(
+            App::new()
+                .wrap(cors(&cors_config()))
+                .wrap(security_headers(false))
+                .route("/api/todos", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/todos")
+            .insert_header((header::ORIGIN, "https://evil.example.com"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(
+            res.headers().get(header::X_CONTENT_TYPE_OPTIONS).unwrap(),
+            "nosniff"
+        );
+        assert!(!res
+            .headers()
+            .contains_key(header::STRICT_TRANSPORT_SECURITY));
+    }
+}
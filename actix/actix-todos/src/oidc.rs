@@ -0,0 +1,821 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use actix_web::{
+    cookie::{time, Cookie, SameSite},
+    error::ErrorInternalServerError,
+    get,
+    http::{header, StatusCode},
+    web::{Data, Query, ServiceConfig},
+    HttpRequest, HttpResponse, ResponseError, Result,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use rand::{distributions::Alphanumeric, Rng};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    auth::encode_jwt,
+    cache::CacheBackend,
+    config::OidcConfig,
+    entity::prelude::{User, UserIdentity},
+    entity::{timestamp, user, user_identity},
+    organizations, AppState,
+};
+
+/// How long a login may take between the redirect to the provider and the
+/// callback.
+const PENDING_TTL: Duration = Duration::from_secs(600);
+const SCOPES: &str = "openid email profile";
+/// Ties a login to the browser that started it, so nobody can finish their
+/// own login in someone else's browser.
+pub const STATE_COOKIE: &str = "oidc_state";
+const STATE_COOKIE_PATH: &str = "/auth/oidc";
+
+#[derive(Debug)]
+pub enum OidcError {
+    Http(reqwest::Error),
+    Token(jsonwebtoken::errors::Error),
+    Db(DbErr),
+    /// Unknown, expired or already used `state`.
+    InvalidState,
+    /// The provider answered with something that is not valid OIDC.
+    Provider(String),
+    /// The identity is new and its email belongs to an existing user, but
+    /// either the provider or the existing account has not verified it.
+    EmailTaken,
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::Http(e) => write!(f, "request to the identity provider failed: {e}"),
+            OidcError::Token(e) => write!(f, "invalid id token: {e}"),
+            OidcError::Db(e) => write!(f, "{e}"),
+            OidcError::InvalidState => write!(f, "invalid or expired login state"),
+            OidcError::Provider(message) => write!(f, "identity provider error: {message}"),
+            OidcError::EmailTaken => write!(
+                f,
+                "email already registered, log in with your password instead"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OidcError {}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(e: reqwest::Error) -> Self {
+        OidcError::Http(e)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for OidcError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        OidcError::Token(e)
+    }
+}
+
+impl From<DbErr> for OidcError {
+    fn from(e: DbErr) -> Self {
+        OidcError::Db(e)
+    }
+}
+
+impl ResponseError for OidcError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            OidcError::InvalidState => StatusCode::BAD_REQUEST,
+            OidcError::Token(_) => StatusCode::FORBIDDEN,
+            OidcError::EmailTaken => StatusCode::CONFLICT,
+            OidcError::Http(_) | OidcError::Provider(_) => StatusCode::BAD_GATEWAY,
+            OidcError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(json!({ "error": self.to_string() }))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+/// Where to send the browser, and the `state` to remember in [`STATE_COOKIE`].
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+}
+
+/// What the callback needs to finish a login, stored under its `state`.
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    verifier: String,
+    nonce: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    nonce: Option<String>,
+}
+
+/// Authorization code flow with PKCE against a single provider. Pending logins
+/// live in a [`CacheBackend`] so every worker can finish a login another one
+/// started.
+#[derive(Clone)]
+pub struct OidcClient {
+    config: OidcConfig,
+    metadata: ProviderMetadata,
+    http: reqwest::Client,
+    pending: Arc<dyn CacheBackend>,
+}
+
+impl OidcClient {
+    /// Reads the provider's endpoints from its discovery document.
+    pub async fn discover(
+        config: OidcConfig,
+        pending: Arc<dyn CacheBackend>,
+    ) -> Result<Self, OidcError> {
+        let http = reqwest::Client::new();
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            config.issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = http
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if metadata.issuer.trim_end_matches('/') != config.issuer.trim_end_matches('/') {
+            return Err(OidcError::Provider(format!(
+                "discovery document is for issuer {}",
+                metadata.issuer
+            )));
+        }
+
+        Ok(OidcClient {
+            config,
+            metadata,
+            http,
+            pending,
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.metadata.issuer
+    }
+
+    /// Starts a login and returns the provider URL to send the browser to.
+    pub async fn authorization_url(&self) -> Result<AuthorizationRequest, OidcError> {
+        let state = random_string(32);
+        let pending = PendingLogin {
+            verifier: random_string(64),
+            nonce: random_string(32),
+        };
+
+        let mut url = reqwest::Url::parse(&self.metadata.authorization_endpoint)
+            .map_err(|e| OidcError::Provider(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", SCOPES)
+            .append_pair("state", &state)
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("code_challenge", &code_challenge(&pending.verifier))
+            .append_pair("code_challenge_method", "S256");
+
+        let pending = serde_json::to_string(&pending).expect("pending login serializes");
+        self.pending
+            .set(&pending_key(&state), pending, PENDING_TTL)
+            .await;
+
+        Ok(AuthorizationRequest {
+            url: url.into(),
+            state,
+        })
+    }
+
+    /// The state cookie only needs `Secure` when the callback is served over
+    /// HTTPS, browsers drop it otherwise.
+    fn state_cookie(&self, value: String) -> Cookie<'static> {
+        Cookie::build(STATE_COOKIE, value)
+            .path(STATE_COOKIE_PATH)
+            .http_only(true)
+            .secure(self.config.redirect_url.starts_with("https://"))
+            .same_site(SameSite::Lax)
+            .max_age(time::Duration::seconds(PENDING_TTL.as_secs() as i64))
+            .finish()
+    }
+
+    /// Redeems the authorization code and returns the validated ID token
+    /// claims. A `state` can only be used once.
+    pub async fn exchange(&self, code: &str, state: &str) -> Result<IdTokenClaims, OidcError> {
+        let key = pending_key(state);
+        let pending = self
+            .pending
+            .get(&key)
+            .await
+            .ok_or(OidcError::InvalidState)?;
+        self.pending.delete(&key).await;
+        let pending: PendingLogin =
+            serde_json::from_str(&pending).map_err(|_| OidcError::InvalidState)?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_url),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", &pending.verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let response: TokenResponse = self
+            .http
+            .post(&self.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let claims = self.verify_id_token(&response.id_token).await?;
+        if claims.nonce.as_deref() != Some(pending.nonce.as_str()) {
+            return Err(OidcError::Provider(
+                "id token nonce does not match".to_string(),
+            ));
+        }
+
+        Ok(claims)
+    }
+
+    /// Checks the signature against the provider's JWKS, plus issuer, audience
+    /// and expiry. Keys are fetched on every login so rotations need no
+    /// restart.
+    async fn verify_id_token(&self, token: &str) -> Result<IdTokenClaims, OidcError> {
+        let header = jsonwebtoken::decode_header(token)?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(OidcError::Provider(
+                "id tokens must be signed with an asymmetric key".to_string(),
+            ));
+        }
+
+        let jwks: JwkSet = self
+            .http
+            .get(&self.metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }
+        .ok_or_else(|| OidcError::Provider("no matching signing key".to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&self.metadata.issuer]);
+
+        Ok(
+            jsonwebtoken::decode::<IdTokenClaims>(
+                token,
+                &DecodingKey::from_jwk(jwk)?,
+                &validation,
+            )?
+            .claims,
+        )
+    }
+}
+
+/// Finds the user linked to the identity, linking it on first login: to the
+/// user with the same email if both the provider and that user have verified
+/// it, otherwise to a new user with their own organisation, like `/register`
+/// creates. `/register` does not check the email, so linking to an unverified
+/// account would hand it to whoever registered it first.
+pub async fn link_user(
+    db: &DatabaseConnection,
+    issuer: &str,
+    claims: &IdTokenClaims,
+) -> Result<user::Model, OidcError> {
+    let identity = UserIdentity::find()
+        .filter(user_identity::Column::Issuer.eq(issuer))
+        .filter(user_identity::Column::Subject.eq(claims.sub.as_str()))
+        .one(db)
+        .await?;
+    if let Some(identity) = identity {
+        return User::find_by_id(identity.user_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| DbErr::RecordNotFound("linked user".to_string()).into());
+    }
+
+    let email = claims
+        .email
+        .as_deref()
+        .ok_or_else(|| OidcError::Provider("id token has no email".to_string()))?;
+
+    let txn = db.begin().await?;
+    let existing = User::find()
+        .filter(user::Column::Email.eq(email))
+        .one(&txn)
+        .await?;
+    let user = match existing {
+        Some(user) if claims.email_verified && user.email_verified_at.is_some() => user,
+        Some(_) => return Err(OidcError::EmailTaken),
+        None => {
+            let user = user::ActiveModel {
+                name: Set(claims.name.clone().unwrap_or_else(|| email.to_string())),
+                email: Set(email.to_string()),
+                // No bcrypt hash verifies against an empty string, so the
+                // account can only log in through the provider.
+                password: Set(String::new()),
+                email_verified_at: Set(claims.email_verified.then(timestamp)),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+            organizations::create_organization(&txn, &user.name, user.id).await?;
+            user
+        }
+    };
+
+    user_identity::ActiveModel {
+        user_id: Set(user.id),
+        issuer: Set(issuer.to_string()),
+        subject: Set(claims.sub.clone()),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(user)
+}
+
+/// Registers `/auth/oidc/login` and `/auth/oidc/callback`.
+pub fn config(cfg: &mut ServiceConfig, client: OidcClient) {
+    cfg.app_data(Data::new(client))
+        .service(login)
+        .service(callback);
+}
+
+#[get("/auth/oidc/login")]
+pub async fn login(client: Data<OidcClient>) -> Result<HttpResponse> {
+    let request = client.authorization_url().await?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, request.url))
+        .cookie(client.state_cookie(request.state))
+        .finish())
+}
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Finishes the login and answers like `/login` does, with our own JWT for
+/// the user's default organisation. The `state` must be the one this browser
+/// was given by [`login`].
+#[get("/auth/oidc/callback")]
+pub async fn callback(
+    req: HttpRequest,
+    query: Query<CallbackQuery>,
+    client: Data<OidcClient>,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let (code, login_state) = match (&query.code, &query.state, &query.error) {
+        (_, _, Some(error)) => {
+            return Ok(HttpResponse::BadRequest().json(json!({ "error": error })))
+        }
+        (Some(code), Some(login_state), None) => (code, login_state),
+        _ => {
+            return Ok(HttpResponse::BadRequest().json(json!({ "error": "missing code or state" })))
+        }
+    };
+
+    match req.cookie(STATE_COOKIE) {
+        Some(cookie) if cookie.value() == login_state => {}
+        _ => return Err(OidcError::InvalidState.into()),
+    }
+
+    let claims = client.exchange(code, login_state).await?;
+    let user = link_user(&state.db, client.issuer(), &claims).await?;
+
+    let organization = organizations::default_organization(&state.db, user.id)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let organization = match organization {
+        Some(organization) => organization,
+        None => {
+            return Ok(HttpResponse::Forbidden()
+                .json(json!({ "error": "not a member of any organization" })))
+        }
+    };
+
+    let token = encode_jwt(user.id as u32, organization).map_err(ErrorInternalServerError)?;
+
+    let mut used_state = client.state_cookie(String::new());
+    used_state.make_removal();
+
+    Ok(HttpResponse::Ok()
+        .cookie(used_state)
+        .json(json!({ "token": token })))
+}
+
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn pending_key(state: &str) -> String {
+    format!("oidc:state:{state}")
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        code_challenge, config, link_user, IdTokenClaims, OidcClient, OidcError, STATE_COOKIE,
+    };
+    use crate::{
+        auth::register_user,
+        cache::MemoryCache,
+        config::OidcConfig,
+        entity::{timestamp, user},
+        organizations,
+        test_util::{self, RecordingMailer},
+        users::verify_email,
+    };
+    use actix_web::{
+        cookie::Cookie,
+        get,
+        http::{header, StatusCode},
+        post, test,
+        web::{self, Data, Form},
+        App, HttpResponse, HttpServer,
+    };
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{ActiveModelTrait, Database, Set};
+    use serde_json::json;
+    use std::{
+        collections::HashMap,
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    const CLIENT_ID: &str = "todos";
+    const KEY_PEM: &[u8] = include_bytes!("../testdata/oidc_rsa.pem");
+    /// Modulus of `testdata/oidc_rsa.pem`, base64url encoded.
+    const KEY_N: &str = "sFMValDI5O3_fQCJLhTo0kd_sOkpIBJPW9FWsHREL17DLDkIF4n7M8UHckLRXGEUj8eMbPHwpITJoWaHC2MhDz1T2ba6n78Av5whVvdg-Cb_AB7YWaKkh9eqQKJEhNYBsYQZbj920gHfVXsgOeY_jimVF6ufczQ5Mhm7-9oq3IoNjO-9ATnpo6FZJiyKe0jaWAwARVMu2GsIOMFOA8y0fzu4jrVHVd_8VZmP7dFBbZIhxbaC1QtK6BXy1Mn9YXRB427EvMwv_OenWCaCsWiQHFLrAan20KI5DL0QNyvs8NOgf-IySifhAN77B8q4YG9vXSUcQC24BcCZYrZUbKEAnw";
+
+    /// The challenge and nonce of the login the mock provider will accept.
+    #[derive(Default)]
+    struct Expected {
+        challenge: String,
+        nonce: String,
+    }
+
+    struct Provider {
+        issuer: String,
+        expected: Mutex<Expected>,
+    }
+
+    #[get("/.well-known/openid-configuration")]
+    async fn discovery(provider: Data<Provider>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "issuer": provider.issuer,
+            "authorization_endpoint": format!("{}/authorize", provider.issuer),
+            "token_endpoint": format!("{}/token", provider.issuer),
+            "jwks_uri": format!("{}/jwks", provider.issuer),
+        }))
+    }
+
+    #[get("/jwks")]
+    async fn jwks() -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "keys": [{ "kty": "RSA", "kid": "test", "alg": "RS256", "use": "sig", "n": KEY_N, "e": "AQAB" }]
+        }))
+    }
+
+    #[post("/token")]
+    async fn token(provider: Data<Provider>, form: Form<HashMap<String, String>>) -> HttpResponse {
+        let expected = provider.expected.lock().unwrap();
+        if form.get("code").map(String::as_str) != Some("code")
+            || form.get("client_id").map(String::as_str) != Some(CLIENT_ID)
+            || form.get("code_verifier").map(|v| code_challenge(v))
+                != Some(expected.challenge.clone())
+        {
+            return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("test".to_string());
+        let id_token = encode(
+            &header,
+            &json!({
+                "iss": provider.issuer,
+                "aud": CLIENT_ID,
+                "sub": "alice-123",
+                "email": "alice@example.com",
+                "email_verified": true,
+                "name": "Alice",
+                "nonce": expected.nonce,
+                "iat": now,
+                "exp": now + 300,
+            }),
+            &EncodingKey::from_rsa_pem(KEY_PEM).unwrap(),
+        )
+        .unwrap();
+
+        HttpResponse::Ok()
+            .json(json!({ "access_token": "x", "token_type": "Bearer", "id_token": id_token }))
+    }
+
+    async fn start_provider() -> Arc<Provider> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let provider = Arc::new(Provider {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            expected: Mutex::default(),
+        });
+
+        let data = Data::from(provider.clone());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .service(discovery)
+                .service(jwks)
+                .service(token)
+                .default_service(web::to(HttpResponse::NotFound))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        provider
+    }
+
+    async fn client(provider: &Provider) -> OidcClient {
+        let config = OidcConfig {
+            issuer: provider.issuer.clone(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost:8080/auth/oidc/callback".to_string(),
+        };
+
+        OidcClient::discover(config, Arc::new(MemoryCache::new(10)))
+            .await
+            .unwrap()
+    }
+
+    /// Tells the provider to expect the login started with `url`, returning
+    /// its `state`.
+    fn expect_login(provider: &Provider, url: &str) -> String {
+        let url = reqwest::Url::parse(url).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(params["client_id"], CLIENT_ID);
+
+        *provider.expected.lock().unwrap() = Expected {
+            challenge: params["code_challenge"].clone(),
+            nonce: params["nonce"].clone(),
+        };
+
+        params["state"].clone()
+    }
+
+    /// Starts a login and tells the provider to expect it, returning `state`.
+    async fn start_login(client: &OidcClient, provider: &Provider) -> String {
+        expect_login(provider, &client.authorization_url().await.unwrap().url)
+    }
+
+    fn claims(email_verified: bool) -> IdTokenClaims {
+        IdTokenClaims {
+            sub: "alice-123".to_string(),
+            email: Some("alice@example.com".to_string()),
+            email_verified,
+            name: None,
+            nonce: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_login_links_identity_once() {
+        let provider = start_provider().await;
+        let client = client(&provider).await;
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+
+        let state = start_login(&client, &provider).await;
+        let claims = client.exchange("code", &state).await.unwrap();
+        assert_eq!(claims.sub, "alice-123");
+        assert!(matches!(
+            client.exchange("code", &state).await,
+            Err(OidcError::InvalidState)
+        ));
+
+        let user = link_user(&db, client.issuer(), &claims).await.unwrap();
+        assert_eq!(user.email, "alice@example.com");
+        assert!(user.email_verified_at.is_some());
+        assert!(organizations::default_organization(&db, user.id)
+            .await
+            .unwrap()
+            .is_some());
+
+        let state = start_login(&client, &provider).await;
+        let claims = client.exchange("code", &state).await.unwrap();
+        assert_eq!(
+            link_user(&db, client.issuer(), &claims).await.unwrap().id,
+            user.id
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_exchange_requires_matching_verifier() {
+        let provider = start_provider().await;
+        let client = client(&provider).await;
+
+        let state = start_login(&client, &provider).await;
+        provider.expected.lock().unwrap().challenge = code_challenge("someone else");
+
+        assert!(matches!(
+            client.exchange("code", &state).await,
+            Err(OidcError::Http(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_link_user_requires_a_verified_local_account() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        // Registered by someone else with the victim's email and a password.
+        let existing = user::ActiveModel {
+            name: Set("mallory".to_string()),
+            email: Set("alice@example.com".to_string()),
+            password: Set("hash".to_string()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            link_user(&db, "https://idp", &claims(true)).await,
+            Err(OidcError::EmailTaken)
+        ));
+
+        let mut verified: user::ActiveModel = existing.clone().into();
+        verified.email_verified_at = Set(Some(timestamp()));
+        verified.update(&db).await.unwrap();
+
+        assert!(matches!(
+            link_user(&db, "https://idp", &claims(false)).await,
+            Err(OidcError::EmailTaken)
+        ));
+        assert_eq!(
+            link_user(&db, "https://idp", &claims(true))
+                .await
+                .unwrap()
+                .id,
+            existing.id
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_registered_user_links_after_verifying_their_email() {
+        let mailer = Arc::new(RecordingMailer::default());
+        let state = test_util::state_with_mailer(mailer.clone()).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state.clone()))
+                .service(register_user)
+                .service(verify_email),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/register")
+            .set_json(
+                json!({ "name": "alice", "email": "alice@example.com", "password": "secret" }),
+            )
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let user_id = body["user"]["id"].as_i64().unwrap() as i32;
+
+        assert!(matches!(
+            link_user(&state.db, "https://idp", &claims(true)).await,
+            Err(OidcError::EmailTaken)
+        ));
+
+        let link = mailer.last_link("alice@example.com").unwrap();
+        let req = test::TestRequest::get().uri(&link).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let user = link_user(&state.db, "https://idp", &claims(true))
+            .await
+            .unwrap();
+        assert_eq!(user.id, user_id);
+    }
+
+    #[actix_web::test]
+    async fn test_callback_requires_the_state_cookie() {
+        let provider = start_provider().await;
+        let client = client(&provider).await;
+        let state = test_util::state().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state))
+                .configure(|cfg| config(cfg, client)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/auth/oidc/login")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FOUND);
+        let cookie = res
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == STATE_COOKIE)
+            .unwrap()
+            .into_owned();
+        assert!(cookie.http_only().unwrap());
+        let location = res
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let login_state = expect_login(&provider, location);
+        assert_eq!(cookie.value(), login_state);
+        let callback = format!("/auth/oidc/callback?code=code&state={login_state}");
+
+        // The attacker's own login, replayed in a browser without the cookie.
+        let req = test::TestRequest::get().uri(&callback).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri(&callback)
+            .cookie(Cookie::new(STATE_COOKIE, "other"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri(&callback)
+            .cookie(cookie)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,238 @@
+use actix_web::{
+    delete,
+    error::{ErrorForbidden, ErrorInternalServerError},
+    get, post,
+    web::{Data, Json, Path},
+    HttpResponse, Result,
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, ModelTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+use serde_json::json;
+
+use crate::{
+    auth::{claims_from_extractor, encode_jwt},
+    entity::prelude::{Organization, OrganizationMember, User},
+    entity::{organization, organization_member, user},
+    AppState,
+};
+
+pub const ROLE_OWNER: &str = "owner";
+pub const ROLE_MEMBER: &str = "member";
+
+/// Creates an organisation with `owner_id` as its only member.
+pub async fn create_organization<C: ConnectionTrait>(
+    db: &C,
+    name: &str,
+    owner_id: i32,
+) -> Result<organization::Model, DbErr> {
+    let organization = organization::ActiveModel {
+        name: Set(name.to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    organization_member::ActiveModel {
+        organization_id: Set(organization.id),
+        user_id: Set(owner_id),
+        role: Set(ROLE_OWNER.to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(organization)
+}
+
+pub async fn find_membership<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    organization_id: i32,
+) -> Result<Option<organization_member::Model>, DbErr> {
+    OrganizationMember::find()
+        .filter(organization_member::Column::UserId.eq(user_id))
+        .filter(organization_member::Column::OrganizationId.eq(organization_id))
+        .one(db)
+        .await
+}
+
+/// The organisation a user is logged into when they do not pick one: the
+/// oldest one they belong to.
+pub async fn default_organization<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<Option<i32>, DbErr> {
+    let membership = OrganizationMember::find()
+        .filter(organization_member::Column::UserId.eq(user_id))
+        .order_by_asc(organization_member::Column::OrganizationId)
+        .one(db)
+        .await?;
+
+    Ok(membership.map(|membership| membership.organization_id))
+}
+
+#[derive(serde::Serialize)]
+pub struct ResponseOrganization {
+    id: i32,
+    name: String,
+    role: String,
+    active: bool,
+}
+
+#[get("/organizations")]
+pub async fn get_organizations(auth: BearerAuth, state: Data<AppState>) -> Result<HttpResponse> {
+    let claims = claims_from_extractor(auth);
+
+    let organizations: Vec<_> = OrganizationMember::find()
+        .filter(organization_member::Column::UserId.eq(claims.sub as i32))
+        .find_also_related(Organization)
+        .all(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .filter_map(|(membership, organization)| {
+            organization.map(|organization| ResponseOrganization {
+                active: organization.id == claims.org,
+                id: organization.id,
+                name: organization.name,
+                role: membership.role,
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({ "organizations": organizations })))
+}
+
+#[derive(serde::Deserialize)]
+pub struct OrganizationRequest {
+    name: String,
+}
+
+#[post("/organizations")]
+pub async fn create(
+    input: Json<OrganizationRequest>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let claims = claims_from_extractor(auth);
+
+    let txn = state.db.begin().await.map_err(ErrorInternalServerError)?;
+    let organization = create_organization(&txn, &input.name, claims.sub as i32)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    txn.commit().await.map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(json!({ "id": organization.id })))
+}
+
+#[derive(serde::Deserialize)]
+pub struct MemberRequest {
+    email: String,
+}
+
+#[post("/organizations/{id}/members")]
+pub async fn add_member(
+    path: Path<i32>,
+    input: Json<MemberRequest>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let organization_id = path.into_inner();
+    require_owner(&state, claims_from_extractor(auth).sub as i32, organization_id).await?;
+
+    let user = User::find()
+        .filter(user::Column::Email.eq(input.email.as_str()))
+        .one(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = match user {
+        Some(user) => user,
+        None => return Ok(HttpResponse::NotFound().json(json!({ "error": "user not found" }))),
+    };
+
+    if find_membership(&state.db, user.id, organization_id)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .is_some()
+    {
+        return Ok(HttpResponse::Conflict().json(json!({ "error": "already a member" })));
+    }
+
+    organization_member::ActiveModel {
+        organization_id: Set(organization_id),
+        user_id: Set(user.id),
+        role: Set(ROLE_MEMBER.to_string()),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/organizations/{id}/members/{user_id}")]
+pub async fn remove_member(
+    path: Path<(i32, i32)>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let (organization_id, user_id) = path.into_inner();
+    let owner_id = claims_from_extractor(auth).sub as i32;
+    require_owner(&state, owner_id, organization_id).await?;
+
+    if user_id == owner_id {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "owners cannot remove themselves" })));
+    }
+
+    match find_membership(&state.db, user_id, organization_id)
+        .await
+        .map_err(ErrorInternalServerError)?
+    {
+        Some(membership) => {
+            membership
+                .delete(&state.db)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        None => Ok(HttpResponse::NotFound().json(json!({ "error": "not a member" }))),
+    }
+}
+
+/// Issues a new token with `id` as the active organisation.
+#[post("/organizations/{id}/switch")]
+pub async fn switch(
+    path: Path<i32>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let organization_id = path.into_inner();
+    let user_id = claims_from_extractor(auth).sub;
+
+    if find_membership(&state.db, user_id as i32, organization_id)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .is_none()
+    {
+        return Err(ErrorForbidden("not a member of this organization"));
+    }
+
+    let token = encode_jwt(user_id, organization_id).map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(json!({ "token": token })))
+}
+
+async fn require_owner(state: &AppState, user_id: i32, organization_id: i32) -> Result<()> {
+    match find_membership(&state.db, user_id, organization_id)
+        .await
+        .map_err(ErrorInternalServerError)?
+    {
+        Some(membership) if membership.role == ROLE_OWNER => Ok(()),
+        _ => Err(ErrorForbidden("only owners can manage members")),
+    }
+}
@@ -0,0 +1,114 @@
+//! Fixtures shared by the handler tests.
+
+use std::{
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use migration::{Migrator, MigratorTrait};
+use sea_orm::Database;
+
+use crate::{
+    blobs::{self, BlobStore, LocalBlobStore},
+    cache::TodoCache,
+    events::TodoEvents,
+    mailer::{LogMailer, Mailer},
+    AppState,
+};
+
+/// The `public_url` of the test state.
+pub const PUBLIC_URL: &str = "http://localhost:8080";
+
+/// A migrated in-memory database and a blob store in a fresh temporary
+/// directory.
+pub async fn state() -> AppState {
+    state_with_mailer(Arc::new(LogMailer)).await
+}
+
+pub async fn state_with_mailer(mailer: Arc<dyn Mailer>) -> AppState {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    Migrator::up(&db, None).await.unwrap();
+
+    AppState {
+        db,
+        events: TodoEvents::new(),
+        cache: TodoCache::default(),
+        blobs: Arc::new(TempBlobStore::default()),
+        mailer,
+        public_url: PUBLIC_URL.to_string(),
+    }
+}
+
+/// A [`LocalBlobStore`] whose directory is removed once the last clone of the
+/// state holding it is dropped.
+#[derive(Debug)]
+pub struct TempBlobStore {
+    root: PathBuf,
+    store: LocalBlobStore,
+}
+
+impl Default for TempBlobStore {
+    fn default() -> Self {
+        let root = std::env::temp_dir().join(format!("actix-todos-blobs-{}", blobs::new_key()));
+        let store = LocalBlobStore::new(&root).unwrap();
+
+        TempBlobStore { root, store }
+    }
+}
+
+impl TempBlobStore {
+    pub fn blob_count(&self) -> usize {
+        std::fs::read_dir(&self.root).unwrap().count()
+    }
+}
+
+impl Drop for TempBlobStore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[async_trait]
+impl BlobStore for TempBlobStore {
+    async fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.store.put(key, data).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        self.store.get(key).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        self.store.delete(key).await
+    }
+}
+
+/// Keeps every message so tests can follow the links in them.
+#[derive(Debug, Default)]
+pub struct RecordingMailer {
+    pub sent: Mutex<Vec<(String, String)>>,
+}
+
+impl RecordingMailer {
+    /// The last link mailed to `to`, as a path and query.
+    pub fn last_link(&self, to: &str) -> Option<String> {
+        let sent = self.sent.lock().unwrap();
+        let (_, body) = sent.iter().rev().find(|(recipient, _)| recipient == to)?;
+        let link = body.split_whitespace().last()?;
+
+        link.strip_prefix(PUBLIC_URL).map(str::to_string)
+    }
+}
+
+#[async_trait]
+impl Mailer for RecordingMailer {
+    async fn send(&self, to: &str, _subject: &str, body: &str) -> io::Result<()> {
+        self.sent
+            .lock()
+            .unwrap()
+            .push((to.to_string(), body.to_string()));
+        Ok(())
+    }
+}
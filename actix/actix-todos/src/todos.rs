@@ -8,7 +8,8 @@ use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set};
 use serde_json::json;
 
 use crate::{
-    auth::id_from_extractor, entity::prelude::Todo, entity::todo, entity::todo::Column::*, AppState,
+    auth::claims_from_extractor, entity::prelude::Todo, entity::todo, entity::todo::Column::*,
+    events::TodoEventKind, AppState,
 };
 
 #[derive(serde::Deserialize)]
@@ -22,20 +23,23 @@ pub async fn create_todo(
     auth: BearerAuth,
     state: Data<AppState>,
 ) -> impl Responder {
-    let user_id = id_from_extractor(auth);
+    let claims = claims_from_extractor(auth);
 
     let todo = todo::ActiveModel {
         name: Set(input.name.clone()),
-        user_id: Set(user_id as i32),
+        user_id: Set(claims.sub as i32),
+        organization_id: Set(claims.org),
         status: Set(false),
         ..Default::default()
     };
 
-    let todo = todo.save(&state.db).await.unwrap();
+    let todo = todo.insert(&state.db).await.unwrap();
+    state.cache.invalidate(todo.organization_id).await;
+    state.events.publish(TodoEventKind::Created, todo.clone());
 
     HttpResponse::Ok().body(
         json!({
-            "id": todo.id.unwrap()
+            "id": todo.id
         })
         .to_string(),
     )
@@ -50,10 +54,15 @@ pub struct RepsonseTodo {
 
 #[get("/todos")]
 pub async fn get_todos(auth: BearerAuth, state: Data<AppState>) -> impl Responder {
-    let user_id = id_from_extractor(auth);
+    let organization_id = claims_from_extractor(auth).org;
+
+    if let Some(body) = state.cache.get(organization_id).await {
+        return HttpResponse::Ok().body(body);
+    }
+    let version = state.cache.version(organization_id).await;
 
     let todos: Vec<_> = Todo::find()
-        .filter(UserId.eq(user_id))
+        .filter(OrganizationId.eq(organization_id))
         .all(&state.db)
         .await
         .unwrap()
@@ -64,23 +73,34 @@ pub async fn get_todos(auth: BearerAuth, state: Data<AppState>) -> impl Responde
             status: todo.status,
         }).collect();
 
-    HttpResponse::Ok().body(json!({ "todos": todos }).to_string())
+    let body = json!({ "todos": todos }).to_string();
+    state.cache.put(organization_id, &version, body.clone()).await;
+
+    HttpResponse::Ok().body(body)
 }
 
 #[patch("/todos/{id}")]
-pub async fn complete_todo(path: Path<i32>, state: Data<AppState>) -> impl Responder {
+pub async fn complete_todo(
+    path: Path<i32>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> impl Responder {
     let id = path.into_inner();
+    let organization_id = claims_from_extractor(auth).org;
 
-    let todo = Todo::find_by_id(id).one(&state.db).await.unwrap();
+    let todo = Todo::find_by_id(id)
+        .filter(OrganizationId.eq(organization_id))
+        .one(&state.db)
+        .await
+        .unwrap();
 
-    match todo {
-        Some(todo) => {
-            let mut todo: todo::ActiveModel = todo.into();
-            todo.status = Set(true);
+    if let Some(todo) = todo {
+        let mut todo: todo::ActiveModel = todo.into();
+        todo.status = Set(true);
 
-            todo.update(&state.db).await.unwrap();
-        }
-        None => {},
+        let todo = todo.update(&state.db).await.unwrap();
+        state.cache.invalidate(todo.organization_id).await;
+        state.events.publish(TodoEventKind::Completed, todo);
     }
 
 
@@ -0,0 +1,632 @@
+use actix_web::{
+    delete,
+    error::ErrorInternalServerError,
+    get, patch, post, put,
+    web::{Data, Json, Query},
+    HttpResponse, Result,
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use bcrypt::verify;
+use chrono::{Duration, NaiveDateTime, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+use serde_json::json;
+
+use crate::{
+    attachments,
+    auth::{hash_password, id_from_extractor},
+    entity::prelude::{
+        Organization, OrganizationMember, Todo, User, UserIdentity, Webhook, WebhookDelivery,
+    },
+    entity::{
+        organization, organization_member, timestamp, todo, user, user_identity, webhook,
+        webhook_delivery, TIMESTAMP_FORMAT,
+    },
+    organizations::ROLE_OWNER,
+    AppState,
+};
+
+/// How long the link sent on registration or by [`update_me`] stays valid.
+const EMAIL_VERIFICATION_TTL_HOURS: i64 = 24;
+
+#[derive(serde::Serialize)]
+pub struct ResponseUser {
+    id: i32,
+    name: String,
+    email: String,
+    email_verified: bool,
+    pending_email: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+impl From<user::Model> for ResponseUser {
+    fn from(user: user::Model) -> Self {
+        ResponseUser {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            email_verified: user.email_verified_at.is_some(),
+            pending_email: user.pending_email,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+#[get("/me")]
+pub async fn get_me(auth: BearerAuth, state: Data<AppState>) -> Result<HttpResponse> {
+    let user = match find_user(&state.db, id_from_extractor(auth)).await? {
+        Some(user) => user,
+        None => return Ok(not_found()),
+    };
+
+    Ok(HttpResponse::Ok().json(json!({ "user": ResponseUser::from(user) })))
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateProfileRequest {
+    name: Option<String>,
+    email: Option<String>,
+}
+
+/// Updates the profile. A new email is not applied straight away, it is kept
+/// as `pending_email` until the link sent to that address is followed.
+#[patch("/me")]
+pub async fn update_me(
+    input: Json<UpdateProfileRequest>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let user = match find_user(&state.db, id_from_extractor(auth)).await? {
+        Some(user) => user,
+        None => return Ok(not_found()),
+    };
+    let input = input.into_inner();
+
+    let email_change = input.email.filter(|email| *email != user.email);
+    if let Some(email) = &email_change {
+        if email_taken(&state.db, email).await? {
+            return Ok(HttpResponse::Conflict().json(json!({ "error": "email already in use" })));
+        }
+    }
+
+    let mut active: user::ActiveModel = user.into();
+    if let Some(name) = input.name {
+        active.name = Set(name);
+    }
+
+    let mut verification = None;
+    if let Some(email) = email_change {
+        let token = verification_token();
+        active.pending_email = Set(Some(email.clone()));
+        active.email_verification_token = Set(Some(token.clone()));
+        active.email_verification_sent_at = Set(Some(timestamp()));
+        verification = Some((email, token));
+    }
+
+    let user = active
+        .update(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some((email, token)) = verification {
+        send_verification_email(&state, &email, &token).await;
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "user": ResponseUser::from(user) })))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChangePasswordRequest {
+    old_password: String,
+    new_password: String,
+}
+
+#[put("/me/password")]
+pub async fn change_password(
+    input: Json<ChangePasswordRequest>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let user = match find_user(&state.db, id_from_extractor(auth)).await? {
+        Some(user) => user,
+        None => return Ok(not_found()),
+    };
+
+    if !verify(&input.old_password, &user.password).unwrap_or(false) {
+        return Ok(HttpResponse::Forbidden().json(json!({ "error": "incorrect password" })));
+    }
+
+    let mut user: user::ActiveModel = user.into();
+    user.password = Set(hash_password(&input.new_password).map_err(ErrorInternalServerError)?);
+    user.update(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Deletes the account, its memberships, webhooks and linked identities.
+/// Organisations left without members are deleted together with their todos
+/// and attachments, todos in shared organisations stay with the organisation.
+/// A shared organisation losing its only owner passes ownership to the member
+/// who joined first.
+#[delete("/me")]
+pub async fn delete_me(auth: BearerAuth, state: Data<AppState>) -> Result<HttpResponse> {
+    let user = match find_user(&state.db, id_from_extractor(auth)).await? {
+        Some(user) => user,
+        None => return Ok(not_found()),
+    };
+
+    let mut storage_keys = Vec::new();
+    let txn = state.db.begin().await.map_err(ErrorInternalServerError)?;
+    let memberships = user
+        .find_related(OrganizationMember)
+        .all(&txn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let organization_ids: Vec<i32> = memberships
+        .iter()
+        .map(|membership| membership.organization_id)
+        .collect();
+    OrganizationMember::delete_many()
+        .filter(organization_member::Column::UserId.eq(user.id))
+        .exec(&txn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    for &organization_id in &organization_ids {
+        let remaining = OrganizationMember::find()
+            .filter(organization_member::Column::OrganizationId.eq(organization_id))
+            .one(&txn)
+            .await
+            .map_err(ErrorInternalServerError)?;
+        if remaining.is_some() {
+            ensure_owner(&txn, organization_id)
+                .await
+                .map_err(ErrorInternalServerError)?;
+        } else {
+            let todo_ids = Todo::find()
+                .filter(todo::Column::OrganizationId.eq(organization_id))
+                .all(&txn)
+                .await
+                .map_err(ErrorInternalServerError)?
+                .into_iter()
+                .map(|todo| todo.id)
+                .collect();
+            storage_keys.extend(
+                attachments::delete_for_todos(&txn, todo_ids)
+                    .await
+                    .map_err(ErrorInternalServerError)?,
+            );
+            Todo::delete_many()
+                .filter(todo::Column::OrganizationId.eq(organization_id))
+                .exec(&txn)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            Organization::delete_many()
+                .filter(organization::Column::Id.eq(organization_id))
+                .exec(&txn)
+                .await
+                .map_err(ErrorInternalServerError)?;
+        }
+    }
+
+    let webhook_ids: Vec<i32> = Webhook::find()
+        .filter(webhook::Column::UserId.eq(user.id))
+        .all(&txn)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(|webhook| webhook.id)
+        .collect();
+    WebhookDelivery::delete_many()
+        .filter(webhook_delivery::Column::WebhookId.is_in(webhook_ids))
+        .exec(&txn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Webhook::delete_many()
+        .filter(webhook::Column::UserId.eq(user.id))
+        .exec(&txn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    UserIdentity::delete_many()
+        .filter(user_identity::Column::UserId.eq(user.id))
+        .exec(&txn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    user.delete(&txn).await.map_err(ErrorInternalServerError)?;
+    txn.commit().await.map_err(ErrorInternalServerError)?;
+    attachments::remove_blobs(state.blobs.as_ref(), &storage_keys).await;
+    for organization_id in organization_ids {
+        state.cache.invalidate(organization_id).await;
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Mails a fresh link, to the pending email if there is one, otherwise to the
+/// current email unless it is already verified.
+#[post("/me/verify-email")]
+pub async fn resend_verification(auth: BearerAuth, state: Data<AppState>) -> Result<HttpResponse> {
+    let user = match find_user(&state.db, id_from_extractor(auth)).await? {
+        Some(user) => user,
+        None => return Ok(not_found()),
+    };
+
+    let email = match (&user.pending_email, &user.email_verified_at) {
+        (Some(email), _) => email.clone(),
+        (None, None) => user.email.clone(),
+        (None, Some(_)) => {
+            return Ok(HttpResponse::Conflict().json(json!({ "error": "email already verified" })))
+        }
+    };
+
+    let token = verification_token();
+    let mut active: user::ActiveModel = user.into();
+    active.email_verification_token = Set(Some(token.clone()));
+    active.email_verification_sent_at = Set(Some(timestamp()));
+    active
+        .update(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    send_verification_email(&state, &email, &token).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(serde::Deserialize)]
+pub struct VerifyEmailQuery {
+    token: String,
+}
+
+#[get("/verify-email")]
+pub async fn verify_email(
+    query: Query<VerifyEmailQuery>,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let user = User::find()
+        .filter(user::Column::EmailVerificationToken.eq(query.token.as_str()))
+        .one(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = match user {
+        Some(user) => user,
+        None => {
+            return Ok(HttpResponse::NotFound().json(json!({ "error": "invalid or used token" })))
+        }
+    };
+    if verification_expired(user.email_verification_sent_at.as_deref()) {
+        return Ok(HttpResponse::Gone().json(json!({ "error": "token expired" })));
+    }
+
+    let mut active: user::ActiveModel = user.clone().into();
+    if let Some(email) = user.pending_email {
+        if email_taken(&state.db, &email).await? {
+            return Ok(HttpResponse::Conflict().json(json!({ "error": "email already in use" })));
+        }
+        active.email = Set(email);
+    }
+    active.pending_email = Set(None);
+    active.email_verification_token = Set(None);
+    active.email_verification_sent_at = Set(None);
+    active.email_verified_at = Set(Some(timestamp()));
+    let user = active
+        .update(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(json!({ "user": ResponseUser::from(user) })))
+}
+
+async fn find_user(db: &DatabaseConnection, id: u32) -> Result<Option<user::Model>> {
+    User::find_by_id(id as i32)
+        .one(db)
+        .await
+        .map_err(ErrorInternalServerError)
+}
+
+async fn email_taken(db: &DatabaseConnection, email: &str) -> Result<bool> {
+    let existing = User::find()
+        .filter(user::Column::Email.eq(email))
+        .one(db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(existing.is_some())
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": "user not found" }))
+}
+
+pub(crate) fn verification_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Tokens without an issue time predate the expiry and are treated as expired.
+fn verification_expired(sent_at: Option<&str>) -> bool {
+    match sent_at.and_then(|sent_at| NaiveDateTime::parse_from_str(sent_at, TIMESTAMP_FORMAT).ok())
+    {
+        Some(sent_at) => {
+            Utc::now().naive_utc() - sent_at > Duration::hours(EMAIL_VERIFICATION_TTL_HOURS)
+        }
+        None => true,
+    }
+}
+
+/// Promotes the longest-standing member if the organisation has no owner left.
+async fn ensure_owner<C: sea_orm::ConnectionTrait>(
+    db: &C,
+    organization_id: i32,
+) -> std::result::Result<(), sea_orm::DbErr> {
+    let members = OrganizationMember::find()
+        .filter(organization_member::Column::OrganizationId.eq(organization_id))
+        .order_by_asc(organization_member::Column::Id)
+        .all(db)
+        .await?;
+    if members.iter().any(|member| member.role == ROLE_OWNER) {
+        return Ok(());
+    }
+
+    if let Some(member) = members.into_iter().next() {
+        let mut member: organization_member::ActiveModel = member.into();
+        member.role = Set(ROLE_OWNER.to_string());
+        member.update(db).await?;
+    }
+
+    Ok(())
+}
+
+/// Mails the link to [`verify_email`]. A failure is only logged, the user can
+/// ask for another link with [`resend_verification`].
+pub(crate) async fn send_verification_email(state: &AppState, email: &str, token: &str) {
+    let body = format!(
+        "Confirm your email address: {}/verify-email?token={token}",
+        state.public_url
+    );
+
+    if let Err(e) = state
+        .mailer
+        .send(email, "Confirm your email address", &body)
+        .await
+    {
+        log::warn!("sending the verification email to {email} failed: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{change_password, delete_me, get_me, resend_verification, update_me, verify_email};
+    use crate::{
+        auth::{encode_jwt, hash_password, verify_jwt},
+        entity::prelude::{OrganizationMember, User},
+        entity::{organization_member, user},
+        organizations::{self, ROLE_MEMBER, ROLE_OWNER},
+        test_util::{self, RecordingMailer},
+        AppState,
+    };
+    use actix_web::{
+        http::{header, StatusCode},
+        test, web, App,
+    };
+    use actix_web_httpauth::middleware::HttpAuthentication;
+    use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    /// Creates a user with password `secret` and their own organisation,
+    /// returns the user and a token for that organisation.
+    async fn create_user(state: &AppState, email: &str) -> (user::Model, String) {
+        let user = user::ActiveModel {
+            name: Set(email.to_string()),
+            email: Set(email.to_string()),
+            password: Set(hash_password("secret").unwrap()),
+            ..Default::default()
+        }
+        .insert(&state.db)
+        .await
+        .unwrap();
+        let organization = organizations::create_organization(&state.db, email, user.id)
+            .await
+            .unwrap();
+        let token = encode_jwt(user.id as u32, organization.id).unwrap();
+
+        (user, token)
+    }
+
+    macro_rules! app {
+        ($state:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::new($state.clone()))
+                    .service(verify_email)
+                    .service(
+                        web::scope("/api")
+                            .wrap(HttpAuthentication::bearer(verify_jwt))
+                            .service(get_me)
+                            .service(update_me)
+                            .service(resend_verification)
+                            .service(change_password)
+                            .service(delete_me),
+                    ),
+            )
+            .await
+        };
+    }
+
+    fn bearer(token: &str) -> (header::HeaderName, String) {
+        (header::AUTHORIZATION, format!("Bearer {token}"))
+    }
+
+    #[actix_web::test]
+    async fn test_get_me() {
+        let state = test_util::state().await;
+        let (_, token) = create_user(&state, "a@example.com").await;
+        let app = app!(state);
+
+        let req = test::TestRequest::get().uri("/api/me").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/api/me")
+            .insert_header(bearer(&token))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["user"]["email"], "a@example.com");
+        assert_eq!(body["user"]["email_verified"], false);
+        assert!(body["user"].get("password").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_change_password() {
+        let state = test_util::state().await;
+        let (user, token) = create_user(&state, "a@example.com").await;
+        let app = app!(state);
+
+        let req = test::TestRequest::put()
+            .uri("/api/me/password")
+            .insert_header(bearer(&token))
+            .set_json(json!({ "old_password": "wrong", "new_password": "changed" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::put()
+            .uri("/api/me/password")
+            .insert_header(bearer(&token))
+            .set_json(json!({ "old_password": "secret", "new_password": "changed" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let user = User::find_by_id(user.id)
+            .one(&state.db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(bcrypt::verify("changed", &user.password).unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_email_change_is_applied_by_the_mailed_link() {
+        let mailer = Arc::new(RecordingMailer::default());
+        let state = test_util::state_with_mailer(mailer.clone()).await;
+        let (_, token) = create_user(&state, "a@example.com").await;
+        let app = app!(state);
+
+        let req = test::TestRequest::patch()
+            .uri("/api/me")
+            .insert_header(bearer(&token))
+            .set_json(json!({ "email": "new@example.com" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["user"]["email"], "a@example.com");
+        assert_eq!(body["user"]["pending_email"], "new@example.com");
+
+        let link = mailer.last_link("new@example.com").unwrap();
+
+        let req = test::TestRequest::get().uri(&link).to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["user"]["email"], "new@example.com");
+        assert_eq!(body["user"]["email_verified"], true);
+        assert_eq!(body["user"]["pending_email"], Value::Null);
+
+        let req = test::TestRequest::get().uri(&link).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_expired_verification_token() {
+        let mailer = Arc::new(RecordingMailer::default());
+        let state = test_util::state_with_mailer(mailer.clone()).await;
+        let (user, token) = create_user(&state, "a@example.com").await;
+        let app = app!(state);
+
+        let mut active: user::ActiveModel = user.into();
+        active.pending_email = Set(Some("new@example.com".to_string()));
+        active.email_verification_token = Set(Some("old-token".to_string()));
+        active.email_verification_sent_at = Set(Some("2000-01-01 00:00:00".to_string()));
+        active.update(&state.db).await.unwrap();
+
+        let req = test::TestRequest::get()
+            .uri("/verify-email?token=old-token")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::GONE);
+
+        let req = test::TestRequest::post()
+            .uri("/api/me/verify-email")
+            .insert_header(bearer(&token))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let link = mailer.last_link("new@example.com").unwrap();
+        let req = test::TestRequest::get().uri(&link).to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["user"]["email"], "new@example.com");
+
+        let req = test::TestRequest::post()
+            .uri("/api/me/verify-email")
+            .insert_header(bearer(&token))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_delete_me_hands_shared_organizations_to_a_member() {
+        let state = test_util::state().await;
+        let (owner, token) = create_user(&state, "a@example.com").await;
+        let (member, _) = create_user(&state, "b@example.com").await;
+        let shared = organizations::create_organization(&state.db, "shared", owner.id)
+            .await
+            .unwrap();
+        organization_member::ActiveModel {
+            organization_id: Set(shared.id),
+            user_id: Set(member.id),
+            role: Set(ROLE_MEMBER.to_string()),
+            ..Default::default()
+        }
+        .insert(&state.db)
+        .await
+        .unwrap();
+        let app = app!(state);
+
+        let req = test::TestRequest::delete()
+            .uri("/api/me")
+            .insert_header(bearer(&token))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        assert!(User::find_by_id(owner.id)
+            .one(&state.db)
+            .await
+            .unwrap()
+            .is_none());
+        let membership = OrganizationMember::find()
+            .filter(organization_member::Column::OrganizationId.eq(shared.id))
+            .one(&state.db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(membership.user_id, member.id);
+        assert_eq!(membership.role, ROLE_OWNER);
+    }
+}
@@ -0,0 +1,507 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use actix_web::{
+    delete,
+    error::ErrorInternalServerError,
+    get, post,
+    web::{Data, Json, Path},
+    HttpResponse, Result,
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set,
+};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    auth::claims_from_extractor,
+    entity::prelude::{Webhook, WebhookDelivery},
+    entity::{timestamp, webhook, webhook_delivery},
+    events::{TodoEvent, TodoEventKind, TodoEvents},
+    organizations::find_membership,
+    AppState,
+};
+
+/// Attempts per event and webhook, including the first one.
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled for every retry after it.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const DELIVERIES_PAGE: u64 = 50;
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+
+#[derive(serde::Serialize)]
+pub struct ResponseWebhook {
+    id: i32,
+    url: String,
+    created_at: Option<String>,
+}
+
+impl From<webhook::Model> for ResponseWebhook {
+    fn from(webhook: webhook::Model) -> Self {
+        ResponseWebhook {
+            id: webhook.id,
+            url: webhook.url,
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ResponseDelivery {
+    id: i32,
+    event: String,
+    attempt: i32,
+    status_code: Option<i32>,
+    error: Option<String>,
+    success: bool,
+    created_at: Option<String>,
+}
+
+impl From<webhook_delivery::Model> for ResponseDelivery {
+    fn from(delivery: webhook_delivery::Model) -> Self {
+        ResponseDelivery {
+            id: delivery.id,
+            event: delivery.event,
+            attempt: delivery.attempt,
+            status_code: delivery.status_code,
+            error: delivery.error,
+            success: delivery.success,
+            created_at: delivery.created_at,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct WebhookRequest {
+    url: String,
+}
+
+/// Registers a webhook for the active organisation. The signing secret is
+/// only ever returned here.
+#[post("/webhooks")]
+pub async fn create(
+    input: Json<WebhookRequest>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let claims = claims_from_extractor(auth);
+
+    let url = match reqwest::Url::parse(&input.url) {
+        Ok(url) => url,
+        Err(_) => return Ok(HttpResponse::BadRequest().json(json!({ "error": "invalid url" }))),
+    };
+    if let Err(e) = resolve_public(&url).await {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": e })));
+    }
+
+    let secret = generate_secret();
+    let webhook = webhook::ActiveModel {
+        user_id: Set(claims.sub as i32),
+        organization_id: Set(claims.org),
+        url: Set(input.url.clone()),
+        secret: Set(secret.clone()),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "webhook": ResponseWebhook::from(webhook),
+        "secret": secret,
+    })))
+}
+
+#[get("/webhooks")]
+pub async fn list(auth: BearerAuth, state: Data<AppState>) -> Result<HttpResponse> {
+    let claims = claims_from_extractor(auth);
+
+    let webhooks: Vec<_> = Webhook::find()
+        .filter(webhook::Column::UserId.eq(claims.sub as i32))
+        .filter(webhook::Column::OrganizationId.eq(claims.org))
+        .order_by_asc(webhook::Column::Id)
+        .all(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(ResponseWebhook::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({ "webhooks": webhooks })))
+}
+
+#[delete("/webhooks/{id}")]
+pub async fn remove(
+    path: Path<i32>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let webhook = match find_webhook(&state.db, auth, path.into_inner()).await? {
+        Some(webhook) => webhook,
+        None => return Ok(not_found()),
+    };
+
+    WebhookDelivery::delete_many()
+        .filter(webhook_delivery::Column::WebhookId.eq(webhook.id))
+        .exec(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    webhook
+        .delete(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// The most recent delivery attempts, newest first.
+#[get("/webhooks/{id}/deliveries")]
+pub async fn deliveries(
+    path: Path<i32>,
+    auth: BearerAuth,
+    state: Data<AppState>,
+) -> Result<HttpResponse> {
+    let webhook = match find_webhook(&state.db, auth, path.into_inner()).await? {
+        Some(webhook) => webhook,
+        None => return Ok(not_found()),
+    };
+
+    let deliveries: Vec<_> = webhook
+        .find_related(WebhookDelivery)
+        .order_by_desc(webhook_delivery::Column::Id)
+        .limit(DELIVERIES_PAGE)
+        .all(&state.db)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(ResponseDelivery::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({ "deliveries": deliveries })))
+}
+
+/// Spawns the background worker that turns todo events into webhook
+/// deliveries. Each delivery runs in its own task so a slow endpoint only
+/// delays its own retries.
+pub fn spawn_worker(db: DatabaseConnection, events: &TodoEvents) {
+    let mut receiver = events.subscribe();
+
+    actix_web::rt::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    if let Err(e) = dispatch(&db, event).await {
+                        log::warn!("webhook dispatch failed: {e}");
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("webhook worker fell behind, skipped {skipped} events")
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+async fn dispatch(db: &DatabaseConnection, event: TodoEvent) -> Result<(), sea_orm::DbErr> {
+    let webhooks = Webhook::find()
+        .filter(webhook::Column::OrganizationId.eq(event.todo.organization_id))
+        .all(db)
+        .await?;
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+
+    let name = event_name(event.kind);
+    let payload = json!({
+        "event": name,
+        "timestamp": timestamp(),
+        "todo": {
+            "id": event.todo.id,
+            "name": event.todo.name,
+            "status": event.todo.status,
+            "user_id": event.todo.user_id,
+            "organization_id": event.todo.organization_id,
+        },
+    })
+    .to_string();
+
+    for webhook in webhooks {
+        // Webhooks of users who have left the organisation stay registered
+        // but must not see its todos any more.
+        if find_membership(db, webhook.user_id, webhook.organization_id)
+            .await?
+            .is_none()
+        {
+            continue;
+        }
+
+        actix_web::rt::spawn(deliver(db.clone(), webhook, name, payload.clone()));
+    }
+
+    Ok(())
+}
+
+/// POSTs `payload` until the endpoint answers with a 2xx status or
+/// [`MAX_ATTEMPTS`] is reached, recording every attempt. The address is
+/// checked again before every attempt, DNS may have changed since the webhook
+/// was registered.
+async fn deliver(
+    db: DatabaseConnection,
+    webhook: webhook::Model,
+    event: &'static str,
+    payload: String,
+) {
+    let signature = format!("sha256={}", sign(&webhook.secret, payload.as_bytes()));
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let response = match delivery_client(&webhook.url).await {
+            Ok(client) => client
+                .post(&webhook.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, event)
+                .header(SIGNATURE_HEADER, &signature)
+                .body(payload.clone())
+                .send()
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+
+        let (status_code, error, success) = match response {
+            Ok(response) => (
+                Some(response.status().as_u16() as i32),
+                None,
+                response.status().is_success(),
+            ),
+            Err(e) => (None, Some(e), false),
+        };
+
+        let recorded = webhook_delivery::ActiveModel {
+            webhook_id: Set(webhook.id),
+            event: Set(event.to_string()),
+            payload: Set(payload.clone()),
+            attempt: Set(attempt as i32),
+            status_code: Set(status_code),
+            error: Set(error),
+            success: Set(success),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await;
+        if let Err(e) = recorded {
+            log::warn!("failed to record delivery for webhook {}: {e}", webhook.id);
+        }
+
+        if success {
+            return;
+        }
+        if attempt < MAX_ATTEMPTS {
+            actix_web::rt::time::sleep(backoff(attempt)).await;
+        }
+    }
+}
+
+/// A client that can only reach the addresses [`resolve_public`] vetted for
+/// `url`, so a second DNS lookup cannot point it elsewhere, and that does not
+/// follow redirects to hosts that were never checked.
+async fn delivery_client(url: &str) -> Result<reqwest::Client, String> {
+    let url = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+    let addrs = resolve_public(&url).await?;
+
+    let mut builder = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none());
+    if let Some(domain) = url.domain() {
+        builder = builder.resolve_to_addrs(domain, &addrs);
+    }
+
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Webhooks are requested by the server, so their URLs must not lead to
+/// loopback, private or link-local addresses such as the cloud metadata
+/// service at 169.254.169.254. Returns the addresses the host resolves to,
+/// all of which are public.
+pub async fn resolve_public(url: &reqwest::Url) -> Result<Vec<SocketAddr>, String> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err("url must be http or https".to_string());
+    }
+    let host = url.host_str().ok_or("url must have a host")?;
+    let port = url.port_or_known_default().ok_or("url must have a port")?;
+
+    let literal = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = match literal.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| format!("cannot resolve {host}: {e}"))?
+            .collect(),
+    };
+
+    if addrs.is_empty() {
+        return Err(format!("cannot resolve {host}"));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        return Err(format!(
+            "{host} resolves to non-public address {}",
+            addr.ip()
+        ));
+    }
+
+    Ok(addrs)
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local fc00::/7 and link-local fe80::/10.
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of `body`, sent as `sha256=<hex>` in
+/// [`SIGNATURE_HEADER`] so receivers can check the payload came from us.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(body);
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Delay after the failed `attempt`: 1s, 2s, 4s, ...
+fn backoff(attempt: u32) -> Duration {
+    BASE_DELAY * 2u32.pow(attempt - 1)
+}
+
+fn event_name(kind: TodoEventKind) -> &'static str {
+    match kind {
+        TodoEventKind::Created => "todo.created",
+        TodoEventKind::Completed => "todo.completed",
+        TodoEventKind::Deleted => "todo.deleted",
+    }
+}
+
+async fn find_webhook(
+    db: &DatabaseConnection,
+    auth: BearerAuth,
+    id: i32,
+) -> Result<Option<webhook::Model>> {
+    let claims = claims_from_extractor(auth);
+
+    Webhook::find_by_id(id)
+        .filter(webhook::Column::UserId.eq(claims.sub as i32))
+        .filter(webhook::Column::OrganizationId.eq(claims.org))
+        .one(db)
+        .await
+        .map_err(ErrorInternalServerError)
+}
+
+fn generate_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect()
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": "webhook not found" }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff, is_public, resolve_public, sign};
+    use std::{net::IpAddr, time::Duration};
+
+    #[test]
+    fn test_sign_matches_rfc_4231() {
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in ["93.184.216.34", "2606:2800:220:1::"] {
+            assert!(is_public(ip.parse::<IpAddr>().unwrap()), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse::<IpAddr>().unwrap()), "{ip}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_resolve_public_rejects_internal_urls() {
+        for url in [
+            "http://127.0.0.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "https://[::1]:8443/hook",
+            "http://localhost:8080/",
+            "ftp://example.com/",
+        ] {
+            let url = reqwest::Url::parse(url).unwrap();
+            assert!(resolve_public(&url).await.is_err(), "{url}");
+        }
+
+        let url = reqwest::Url::parse("https://93.184.216.34/hook").unwrap();
+        assert_eq!(
+            resolve_public(&url).await.unwrap(),
+            vec!["93.184.216.34:443".parse().unwrap()]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events sent to every member of the room, as JSON tagged with `type`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatEvent {
+    Join { username: String },
+    Leave { username: String },
+    Message { username: String, text: String },
+}
+
+/// A single in-memory chat room. Only messages are kept in the history, the
+/// most recent `history_len` of them.
+#[derive(Clone)]
+pub struct ChatRoom {
+    sender: broadcast::Sender<ChatEvent>,
+    history: Arc<Mutex<VecDeque<ChatEvent>>>,
+    history_len: usize,
+}
+
+impl ChatRoom {
+    pub fn new(history_len: usize) -> Self {
+        let (sender, _) = broadcast::channel(256);
+
+        Self {
+            sender,
+            history: Arc::default(),
+            history_len,
+        }
+    }
+
+    /// Subscribes before announcing the join, so the new member sees its own
+    /// join and nothing posted in between is lost. Returns the history to
+    /// replay first.
+    pub fn join(&self, username: &str) -> (Vec<ChatEvent>, broadcast::Receiver<ChatEvent>) {
+        let receiver = self.sender.subscribe();
+        let history = self.history.lock().unwrap().iter().cloned().collect();
+
+        self.publish(ChatEvent::Join {
+            username: username.to_string(),
+        });
+
+        (history, receiver)
+    }
+
+    pub fn leave(&self, username: &str) {
+        self.publish(ChatEvent::Leave {
+            username: username.to_string(),
+        });
+    }
+
+    pub fn post(&self, username: &str, text: String) {
+        let event = ChatEvent::Message {
+            username: username.to_string(),
+            text,
+        };
+
+        let mut history = self.history.lock().unwrap();
+        history.push_back(event.clone());
+        while history.len() > self.history_len {
+            history.pop_front();
+        }
+        drop(history);
+
+        self.publish(event);
+    }
+
+    fn publish(&self, event: ChatEvent) {
+        // Fails only when nobody is connected.
+        let _ = self.sender.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChatEvent, ChatRoom};
+
+    fn message(username: &str, text: &str) -> ChatEvent {
+        ChatEvent::Message {
+            username: username.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_room_broadcast_and_history() {
+        let room = ChatRoom::new(2);
+
+        let (history, mut alice) = room.join("alice");
+        assert!(history.is_empty());
+        assert_eq!(
+            alice.recv().await.unwrap(),
+            ChatEvent::Join {
+                username: "alice".to_string()
+            }
+        );
+
+        room.post("alice", "one".to_string());
+        room.post("alice", "two".to_string());
+        room.post("alice", "three".to_string());
+
+        let (history, mut bob) = room.join("bob");
+        assert_eq!(
+            history,
+            vec![message("alice", "two"), message("alice", "three")]
+        );
+
+        room.leave("bob");
+        assert_eq!(
+            bob.recv().await.unwrap(),
+            ChatEvent::Join {
+                username: "bob".to_string()
+            }
+        );
+        assert_eq!(
+            bob.recv().await.unwrap(),
+            ChatEvent::Leave {
+                username: "bob".to_string()
+            }
+        );
+
+        assert_eq!(alice.recv().await.unwrap(), message("alice", "one"));
+    }
+}
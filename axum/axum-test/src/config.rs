@@ -0,0 +1,273 @@
+use std::{net::SocketAddr, path::PathBuf, str::FromStr};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+
+use crate::{
+    crypt::pwd,
+    web::{mw_csrf::Csrf, routes_static::StaticConfig},
+    Error, Result,
+};
+
+/// Read when `CONFIG_FILE` is not set and the file exists.
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Shortest accepted auth-token key, in bytes. HMAC takes keys of any length,
+/// including an empty one, which would make tokens forgeable.
+pub const MIN_AUTH_TOKEN_KEY_LEN: usize = 32;
+
+/// Server configuration. Values come from the defaults, then the TOML config
+/// file, then environment variables, each overriding the one before.
+#[derive(Debug)]
+pub struct Config {
+    /// `address` / `ADDRESS`.
+    pub address: SocketAddr,
+    /// `static_dir` / `STATIC_DIR`.
+    pub static_dir: PathBuf,
+    /// `spa_fallback` / `SPA_FALLBACK`.
+    pub spa_fallback: bool,
+    /// Key signing the auth-token cookie, base64url in `auth_token_key` /
+    /// `AUTH_TOKEN_KEY`, at least [`MIN_AUTH_TOKEN_KEY_LEN`] bytes. A random
+    /// key is used when unset.
+    pub auth_token_key: Option<Vec<u8>>,
+    /// `database_url` / `DATABASE_URL`, tickets stay in memory when unset.
+    pub database_url: Option<String>,
+    /// `request_log_file` / `REQUEST_LOG_FILE`, request logs go to stdout
+    /// when unset.
+    pub request_log_file: Option<PathBuf>,
+    /// Origins besides the server's own that may send unsafe requests,
+    /// `allowed_origins` / `ALLOWED_ORIGINS` (comma separated).
+    pub allowed_origins: Vec<String>,
+    /// `[[users]]`, only read from the config file. Nobody can log in when
+    /// it is empty.
+    pub users: Vec<UserConfig>,
+}
+
+/// A user who can log in, with a bcrypt hash from `axum-test hash-pwd`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    pub username: String,
+    pub pwd_hash: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            static_dir: PathBuf::from("public"),
+            spa_fallback: false,
+            auth_token_key: None,
+            database_url: None,
+            request_log_file: None,
+            allowed_origins: Vec::new(),
+            users: Vec::new(),
+        }
+    }
+}
+
+/// The config file, every key is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    address: Option<SocketAddr>,
+    static_dir: Option<PathBuf>,
+    spa_fallback: Option<bool>,
+    auth_token_key: Option<String>,
+    database_url: Option<String>,
+    request_log_file: Option<PathBuf>,
+    allowed_origins: Option<Vec<String>>,
+    users: Option<Vec<UserConfig>>,
+}
+
+impl Config {
+    /// Loads `CONFIG_FILE`, or [`DEFAULT_CONFIG_FILE`] if present, and applies
+    /// the environment on top.
+    pub fn load() -> Result<Self> {
+        let file = match std::env::var("CONFIG_FILE") {
+            Ok(path) => Some(read_file(&path)?),
+            Err(_) => match std::fs::read_to_string(DEFAULT_CONFIG_FILE) {
+                Ok(content) => Some(content),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(Error::ConfigReadFail(e.to_string())),
+            },
+        };
+
+        Self::from_sources(file.as_deref(), |name| std::env::var(name).ok())
+    }
+
+    fn from_sources(file: Option<&str>, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let file: ConfigFile = match file {
+            Some(content) => {
+                toml::from_str(content).map_err(|e| Error::ConfigReadFail(e.to_string()))?
+            }
+            None => ConfigFile::default(),
+        };
+        let defaults = Config::default();
+
+        let auth_token_key = match env("AUTH_TOKEN_KEY").or(file.auth_token_key) {
+            Some(key) => {
+                let key = URL_SAFE_NO_PAD
+                    .decode(key)
+                    .map_err(|_| Error::ConfigWrongFormat("AUTH_TOKEN_KEY"))?;
+                if key.len() < MIN_AUTH_TOKEN_KEY_LEN {
+                    return Err(Error::ConfigAuthTokenKeyTooShort { len: key.len() });
+                }
+                Some(key)
+            }
+            None => None,
+        };
+
+        let users = file.users.unwrap_or_default();
+        if users.iter().any(|user| !pwd::is_pwd_hash(&user.pwd_hash)) {
+            return Err(Error::ConfigWrongFormat("users.pwd_hash"));
+        }
+
+        Ok(Self {
+            address: parse_env(&env, "ADDRESS")?
+                .or(file.address)
+                .unwrap_or(defaults.address),
+            static_dir: parse_env(&env, "STATIC_DIR")?
+                .or(file.static_dir)
+                .unwrap_or(defaults.static_dir),
+            spa_fallback: parse_env(&env, "SPA_FALLBACK")?
+                .or(file.spa_fallback)
+                .unwrap_or(defaults.spa_fallback),
+            auth_token_key,
+            database_url: env("DATABASE_URL").or(file.database_url),
+            request_log_file: parse_env(&env, "REQUEST_LOG_FILE")?.or(file.request_log_file),
+            allowed_origins: env("ALLOWED_ORIGINS")
+                .map(|origins| {
+                    origins
+                        .split(',')
+                        .map(|origin| origin.trim().to_string())
+                        .filter(|origin| !origin.is_empty())
+                        .collect()
+                })
+                .or(file.allowed_origins)
+                .unwrap_or_default(),
+            users,
+        })
+    }
+
+    pub fn csrf(&self) -> Csrf {
+        Csrf::new(self.allowed_origins.clone())
+    }
+
+    pub fn static_config(&self) -> StaticConfig {
+        StaticConfig {
+            root: self.static_dir.clone(),
+            spa_fallback: self.spa_fallback,
+        }
+    }
+}
+
+fn read_file(path: &str) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|e| Error::ConfigReadFail(format!("{path}: {e}")))
+}
+
+fn parse_env<T: FromStr>(
+    env: &impl Fn(&str) -> Option<String>,
+    name: &'static str,
+) -> Result<Option<T>> {
+    env(name)
+        .map(|value| value.parse().map_err(|_| Error::ConfigWrongFormat(name)))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::Error;
+    use std::{collections::HashMap, net::SocketAddr, path::PathBuf};
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let config = Config::from_sources(None, env(&[])).unwrap();
+
+        assert_eq!(config.address, SocketAddr::from(([127, 0, 0, 1], 8080)));
+        assert_eq!(config.static_dir, PathBuf::from("public"));
+        assert!(!config.spa_fallback);
+        assert!(config.auth_token_key.is_none());
+    }
+
+    #[test]
+    fn test_config_env_overrides_file() {
+        let file = r#"
+            address = "0.0.0.0:3000"
+            static_dir = "dist"
+            spa_fallback = true
+            auth_token_key = "c2VjcmV0LXNlY3JldC1zZWNyZXQtc2VjcmV0LXNlY3JldA"
+            allowed_origins = ["https://app.example.com"]
+        "#;
+        let config =
+            Config::from_sources(Some(file), env(&[("ADDRESS", "127.0.0.1:4000")])).unwrap();
+
+        assert_eq!(config.address, SocketAddr::from(([127, 0, 0, 1], 4000)));
+        assert_eq!(config.static_dir, PathBuf::from("dist"));
+        assert!(config.spa_fallback);
+        assert_eq!(
+            config.auth_token_key.as_deref(),
+            Some(&b"secret-secret-secret-secret-secret"[..])
+        );
+        assert_eq!(config.allowed_origins, vec!["https://app.example.com"]);
+    }
+
+    #[test]
+    fn test_config_wrong_format() {
+        assert!(matches!(
+            Config::from_sources(None, env(&[("SPA_FALLBACK", "yes")])),
+            Err(Error::ConfigWrongFormat("SPA_FALLBACK"))
+        ));
+        assert!(matches!(
+            Config::from_sources(Some("adress = \"x\""), env(&[])),
+            Err(Error::ConfigReadFail(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_rejects_short_auth_token_key() {
+        assert!(matches!(
+            Config::from_sources(None, env(&[("AUTH_TOKEN_KEY", "")])),
+            Err(Error::ConfigAuthTokenKeyTooShort { len: 0 })
+        ));
+        assert!(matches!(
+            Config::from_sources(Some("auth_token_key = \"c2VjcmV0\""), env(&[])),
+            Err(Error::ConfigAuthTokenKeyTooShort { len: 6 })
+        ));
+
+        let key = "A".repeat(43); // 43 base64url characters decode to 32 bytes.
+        let config = Config::from_sources(None, env(&[("AUTH_TOKEN_KEY", &key)])).unwrap();
+        assert_eq!(config.auth_token_key.map(|key| key.len()), Some(32));
+    }
+
+    #[test]
+    fn test_config_users() {
+        let file = r#"
+            [[users]]
+            username = "tissy"
+            pwd_hash = "$2b$04$EGdrhbKUv8Oc9vGiXX0HQOxSg445d/uX6Xw.2eH7jq3pYyrnqMlmG"
+        "#;
+        let config = Config::from_sources(Some(file), env(&[])).unwrap();
+        assert_eq!(config.users.len(), 1);
+        assert_eq!(config.users[0].username, "tissy");
+
+        let file = r#"
+            [[users]]
+            username = "tissy"
+            pwd_hash = "secret"
+        "#;
+        assert!(matches!(
+            Config::from_sources(Some(file), env(&[])),
+            Err(Error::ConfigWrongFormat("users.pwd_hash"))
+        ));
+    }
+}
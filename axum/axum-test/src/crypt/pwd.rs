@@ -0,0 +1,20 @@
+use crate::{Error, Result};
+
+pub use bcrypt::DEFAULT_COST;
+
+/// The cheapest cost bcrypt accepts, only meant for tests.
+pub const MIN_COST: u32 = 4;
+
+pub fn hash_pwd(pwd: &str, cost: u32) -> Result<String> {
+    bcrypt::hash(pwd, cost).map_err(|_| Error::PwdHashFail)
+}
+
+/// Whether `pwd_hash` is a well-formed bcrypt hash.
+pub fn is_pwd_hash(pwd_hash: &str) -> bool {
+    pwd_hash.parse::<bcrypt::HashParts>().is_ok()
+}
+
+/// A malformed stored hash counts as a mismatch.
+pub fn verify_pwd(pwd: &str, pwd_hash: &str) -> bool {
+    bcrypt::verify(pwd, pwd_hash).unwrap_or(false)
+}
@@ -0,0 +1,142 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::{Error, Result};
+
+/// How long a freshly issued auth token stays valid.
+pub const TOKEN_DURATION_SEC: u64 = 30 * 60;
+
+/// The `auth-token` cookie value: `user-<user_id>.<exp>.<sign>`, where `exp`
+/// is a unix timestamp and `sign` the base64url HMAC-SHA256 of the part before
+/// it.
+#[derive(Debug)]
+pub struct Token {
+    pub user_id: u64,
+    pub exp: u64,
+    pub sign: String,
+}
+
+impl FromStr for Token {
+    type Err = Error;
+
+    fn from_str(token: &str) -> Result<Self> {
+        let (content, sign) = token
+            .rsplit_once('.')
+            .ok_or(Error::AuthFailTokenWrongFormat)?;
+        let (user, exp) = content
+            .split_once('.')
+            .ok_or(Error::AuthFailTokenWrongFormat)?;
+        let user_id = user
+            .strip_prefix("user-")
+            .and_then(|id| id.parse().ok())
+            .ok_or(Error::AuthFailTokenWrongFormat)?;
+        let exp = exp.parse().map_err(|_| Error::AuthFailTokenWrongFormat)?;
+
+        Ok(Token {
+            user_id,
+            exp,
+            sign: sign.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "user-{}.{}.{}", self.user_id, self.exp, self.sign)
+    }
+}
+
+pub fn generate_web_token(user_id: u64) -> Token {
+    let exp = now_sec() + TOKEN_DURATION_SEC;
+    let sign = URL_SAFE_NO_PAD.encode(mac(user_id, exp).finalize().into_bytes());
+
+    Token { user_id, exp, sign }
+}
+
+/// Checks the signature first, so the expiry of a forged token is never
+/// trusted.
+pub fn validate_web_token(token: &Token) -> Result<()> {
+    let sign = URL_SAFE_NO_PAD
+        .decode(&token.sign)
+        .map_err(|_| Error::AuthFailTokenSignNotMatching)?;
+    mac(token.user_id, token.exp)
+        .verify_slice(&sign)
+        .map_err(|_| Error::AuthFailTokenSignNotMatching)?;
+
+    if token.exp <= now_sec() {
+        return Err(Error::AuthFailTokenExpired);
+    }
+
+    Ok(())
+}
+
+fn mac(user_id: u64, exp: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(token_key()).expect("hmac takes any key size");
+    mac.update(format!("user-{user_id}.{exp}").as_bytes());
+    mac
+}
+
+static TOKEN_KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Sets the key tokens are signed with. Must run before the first token is
+/// generated or validated, returns `false` when it is too late.
+pub fn set_token_key(key: Vec<u8>) -> bool {
+    TOKEN_KEY.set(key).is_ok()
+}
+
+/// The key from [`set_token_key`], or a random key when none was set, which
+/// logs everybody out on restart.
+fn token_key() -> &'static [u8] {
+    TOKEN_KEY.get_or_init(|| {
+        eprintln!("no auth token key set, using a random key");
+        let mut key = vec![0; 64];
+        rand::thread_rng().fill_bytes(&mut key);
+        key
+    })
+}
+
+fn now_sec() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_web_token, validate_web_token, Token};
+    use crate::Error;
+
+    #[test]
+    fn test_token_round_trip() {
+        let token = generate_web_token(1).to_string();
+        let token: Token = token.parse().unwrap();
+
+        assert_eq!(token.user_id, 1);
+        assert!(validate_web_token(&token).is_ok());
+    }
+
+    #[test]
+    fn test_token_rejects_tampering() {
+        let mut token = generate_web_token(1);
+        token.user_id = 2;
+        assert!(matches!(
+            validate_web_token(&token),
+            Err(Error::AuthFailTokenSignNotMatching)
+        ));
+
+        assert!(matches!(
+            "user-1.exp.sign".parse::<Token>(),
+            Err(Error::AuthFailTokenWrongFormat)
+        ));
+    }
+}
@@ -0,0 +1,15 @@
+/// The authenticated caller, resolved from the auth-token cookie.
+#[derive(Clone, Debug)]
+pub struct Ctx {
+    user_id: u64,
+}
+
+impl Ctx {
+    pub fn new(user_id: u64) -> Self {
+        Self { user_id }
+    }
+
+    pub fn user_id(&self) -> u64 {
+        self.user_id
+    }
+}
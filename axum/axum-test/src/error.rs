@@ -1,17 +1,130 @@
-use axum::{response::IntoResponse, http::StatusCode};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
 
+use crate::validate::FieldError;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
-#[derive(Debug)]
+/// Server side errors. They carry the detail for the logs, what the client
+/// gets to see is decided by [`Error::client_status_and_error`].
+#[derive(Clone, Debug)]
 pub enum Error {
     LoginFail,
+    UsernameTaken,
+    PwdHashFail,
+    StoreFail(String),
+
+    // -- Config errors.
+    ConfigReadFail(String),
+    ConfigWrongFormat(&'static str),
+    ConfigAuthTokenKeyTooShort { len: usize },
+
+    // -- Auth errors.
+    AuthFailNoAuthTokenCookie,
+    AuthFailTokenWrongFormat,
+    AuthFailTokenSignNotMatching,
+    AuthFailTokenExpired,
+    AuthFailUserNotFound,
+    AuthFailCtxNotInRequestExt,
+    CsrfFailOriginNotAllowed,
+    CsrfFailTokenNotMatching,
+
+    // -- Input errors.
+    ValidationFail(Vec<FieldError>),
+
+    // -- Model errors.
+    TicketDeleteFailIdNotFound { id: u64 },
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(fmt, "{self:?}")
+    }
 }
 
+impl std::error::Error for Error {}
+
+/// Responds with a bare 500 and stashes the error in the response extensions,
+/// `main_response_mapper` turns it into the client error body.
 impl IntoResponse for Error {
-    fn into_response(self) -> axum::response::Response {
-        println!("->> {:<12} - {self:?}", "INTO_RES");
-        
-        (StatusCode::INTERNAL_SERVER_ERROR, "UNHANDLED_CLIENT_ERROR").into_response()
+    fn into_response(self) -> Response {
+        let mut response = StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        response.extensions_mut().insert(self);
+
+        response
+    }
+}
+
+impl Error {
+    pub fn client_status_and_error(&self) -> (StatusCode, ClientError) {
+        #[allow(unreachable_patterns)]
+        match self {
+            Self::LoginFail => (StatusCode::FORBIDDEN, ClientError::LOGIN_FAIL),
+
+            Self::AuthFailNoAuthTokenCookie
+            | Self::AuthFailTokenWrongFormat
+            | Self::AuthFailTokenSignNotMatching
+            | Self::AuthFailTokenExpired
+            | Self::AuthFailUserNotFound
+            | Self::AuthFailCtxNotInRequestExt => (StatusCode::FORBIDDEN, ClientError::NO_AUTH),
+
+            Self::CsrfFailOriginNotAllowed | Self::CsrfFailTokenNotMatching => {
+                (StatusCode::FORBIDDEN, ClientError::CSRF_FAIL)
+            }
+
+            Self::ValidationFail(fields) => (
+                StatusCode::BAD_REQUEST,
+                ClientError::INVALID_INPUT(fields.clone()),
+            ),
+
+            Self::UsernameTaken => (StatusCode::CONFLICT, ClientError::INVALID_PARAMS),
+            Self::TicketDeleteFailIdNotFound { .. } => {
+                (StatusCode::NOT_FOUND, ClientError::NOT_FOUND)
+            }
+
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ClientError::SERVICE_ERROR,
+            ),
+        }
+    }
+}
+
+/// The error types a client can see, sent as `error.type`.
+#[derive(Debug)]
+#[allow(non_camel_case_types)]
+pub enum ClientError {
+    LOGIN_FAIL,
+    NO_AUTH,
+    CSRF_FAIL,
+    INVALID_PARAMS,
+    NOT_FOUND,
+    /// Malformed or invalid input, sent with `error.fields`.
+    INVALID_INPUT(Vec<FieldError>),
+    SERVICE_ERROR,
+}
+
+impl ClientError {
+    pub fn fields(&self) -> Option<&[FieldError]> {
+        match self {
+            Self::INVALID_INPUT(fields) => Some(fields),
+            _ => None,
+        }
     }
-}
\ No newline at end of file
+}
+
+impl AsRef<str> for ClientError {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::LOGIN_FAIL => "LOGIN_FAIL",
+            Self::NO_AUTH => "NO_AUTH",
+            Self::CSRF_FAIL => "CSRF_FAIL",
+            Self::INVALID_PARAMS => "INVALID_PARAMS",
+            Self::NOT_FOUND => "NOT_FOUND",
+            Self::INVALID_INPUT(_) => "INVALID_INPUT",
+            Self::SERVICE_ERROR => "SERVICE_ERROR",
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use axum::{middleware, Router};
+use tower_cookies::CookieManagerLayer;
+
+pub use self::error::{Error, Result};
+
+use crate::{
+    chat::ChatRoom,
+    log::RequestLogger,
+    model::ModelController,
+    users::UserStore,
+    web::{mw_csrf::Csrf, routes_static::StaticConfig},
+};
+
+pub mod chat;
+pub mod config;
+pub mod crypt;
+pub mod ctx;
+pub mod error;
+pub mod log;
+pub mod model;
+pub mod users;
+pub mod validate;
+pub mod web;
+
+/// Messages the chat room replays to members who join.
+const CHAT_HISTORY_LEN: usize = 100;
+
+/// The whole application router, `main` only adds the listener. Tests drive it
+/// in-process.
+pub fn app(
+    users: UserStore,
+    mc: ModelController,
+    logger: RequestLogger,
+    static_config: &StaticConfig,
+    csrf: Csrf,
+) -> Router {
+    // Login has no token to send yet, the origin check covers it. Its response
+    // hands out the token the /api routes then require.
+    let routes_login = web::routes_login::routes(users.clone()).route_layer(
+        middleware::from_fn_with_state(csrf.clone(), web::mw_csrf::mw_csrf),
+    );
+
+    let routes_ws =
+        web::routes_ws::routes(users.clone(), ChatRoom::new(CHAT_HISTORY_LEN), csrf.clone());
+
+    let routes_apis = web::routes_user::routes(users.clone())
+        .merge(web::routes_tickets::routes(mc))
+        .route_layer(middleware::from_fn(web::mw_auth::mw_require_auth))
+        .route_layer(middleware::from_fn_with_state(
+            csrf.with_token(),
+            web::mw_csrf::mw_csrf,
+        ));
+
+    Router::new()
+        .merge(web::routes_hello::routes())
+        .merge(routes_login)
+        .merge(routes_ws)
+        .nest("/api", routes_apis)
+        .layer(middleware::map_response_with_state(
+            logger,
+            web::mw_res_map::main_response_mapper,
+        ))
+        .layer(middleware::from_fn_with_state(
+            users,
+            web::mw_auth::mw_ctx_resolver,
+        ))
+        .layer(CookieManagerLayer::new())
+        .fallback_service(web::routes_static::routes(static_config))
+}
@@ -0,0 +1,148 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::{Method, StatusCode, Uri};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{ctx::Ctx, error::ClientError, Error};
+
+/// Writes one JSON line per request, to stdout or appended to a file. Other
+/// messages go to stderr, so stdout stays valid JSON Lines.
+#[derive(Clone, Default)]
+pub struct RequestLogger {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl RequestLogger {
+    pub fn stdout() -> Self {
+        Self::default()
+    }
+
+    pub fn file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(file))),
+        })
+    }
+
+    fn write(&self, line: &RequestLogLine) {
+        let json = serde_json::to_string(line).expect("request log line serializes");
+
+        let result = match &self.file {
+            Some(file) => writeln!(file.lock().unwrap(), "{json}"),
+            None => writeln!(io::stdout().lock(), "{json}"),
+        };
+        if let Err(e) = result {
+            eprintln!("failed to write request log line: {e}");
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RequestLogLine {
+    /// Milliseconds since the unix epoch.
+    timestamp: u128,
+    uuid: String,
+
+    http_method: String,
+    http_uri: String,
+    http_status: u16,
+
+    user_id: Option<u64>,
+
+    error_type: Option<String>,
+    client_error_type: Option<String>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn log_request(
+    logger: &RequestLogger,
+    uuid: Uuid,
+    method: &Method,
+    uri: &Uri,
+    status: StatusCode,
+    ctx: Option<&Ctx>,
+    service_error: Option<&Error>,
+    client_error: Option<&ClientError>,
+) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    logger.write(&RequestLogLine {
+        timestamp,
+        uuid: uuid.to_string(),
+        http_method: method.to_string(),
+        http_uri: uri.to_string(),
+        http_status: status.as_u16(),
+        user_id: ctx.map(Ctx::user_id),
+        error_type: service_error.map(|error| format!("{error:?}")),
+        client_error_type: client_error.map(|client_error| client_error.as_ref().to_string()),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{log_request, RequestLogger};
+    use crate::{ctx::Ctx, error::ClientError, Error};
+    use axum::http::{Method, StatusCode, Uri};
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_log_request_appends_json_line() {
+        let path = std::env::temp_dir().join(format!("axum-test-log-{}.jsonl", Uuid::new_v4()));
+        let logger = RequestLogger::file(&path).unwrap();
+        let uuid = Uuid::new_v4();
+
+        log_request(
+            &logger,
+            uuid,
+            &Method::DELETE,
+            &Uri::from_static("/api/tickets/3"),
+            StatusCode::BAD_REQUEST,
+            Some(&Ctx::new(7)),
+            Some(&Error::TicketDeleteFailIdNotFound { id: 3 }),
+            Some(&ClientError::INVALID_PARAMS),
+        );
+        log_request(
+            &logger,
+            Uuid::new_v4(),
+            &Method::GET,
+            &Uri::from_static("/hello"),
+            StatusCode::OK,
+            None,
+            None,
+            None,
+        );
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["uuid"], uuid.to_string());
+        assert_eq!(lines[0]["http_method"], "DELETE");
+        assert_eq!(lines[0]["http_uri"], "/api/tickets/3");
+        assert_eq!(lines[0]["http_status"], 400);
+        assert_eq!(lines[0]["user_id"], 7);
+        assert_eq!(
+            lines[0]["error_type"],
+            "TicketDeleteFailIdNotFound { id: 3 }"
+        );
+        assert_eq!(lines[0]["client_error_type"], "INVALID_PARAMS");
+        assert!(lines[1]["user_id"].is_null());
+        assert!(lines[1]["error_type"].is_null());
+    }
+}
@@ -1,69 +1,112 @@
-#![allow(unused)]
+use std::{io::BufRead, sync::Arc};
+
+use sqlx::sqlite::SqlitePoolOptions;
+use tokio::net::TcpListener;
+
+use axum_test::{
+    config::Config,
+    crypt::{pwd, token},
+    log::RequestLogger,
+    model::{MemoryTicketStore, ModelController, SqliteTicketStore, TicketStore},
+    users::UserStore,
+    Error,
+};
 
-use std::net::SocketAddr;
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if std::env::args().nth(1).as_deref() == Some("hash-pwd") {
+        return hash_pwd();
+    }
 
-use axum::{
-    extract::{Path, Query},
-    middleware,
-    response::{Html, IntoResponse, Response},
-    routing::{get, get_service},
-    Router,
-};
-use serde::Deserialize;
-use tower_cookies::CookieManagerLayer;
-use tower_http::services::ServeDir;
+    let config = Config::load()?;
 
-pub use self::error::{Error, Result};
+    if let Some(key) = config.auth_token_key.clone() {
+        token::set_token_key(key);
+    }
 
-mod error;
-mod web;
+    let users = UserStore::new();
+    for user in &config.users {
+        users.insert(&user.username, user.pwd_hash.clone())?;
+    }
+    if config.users.is_empty() {
+        eprintln!("no [[users]] in the config file, nobody can log in");
+    }
 
-#[tokio::main]
-async fn main() {
-    let routes = Router::new()
-        .merge(routes_hello())
-        .merge(web::routes_login::routes())
-        .layer(middleware::map_response(main_response_mapper))
-        .layer(CookieManagerLayer::new())
-        .fallback_service(routes_static());
+    let mc = ModelController::new(ticket_store(&config).await?);
+    let logger = match &config.request_log_file {
+        Some(path) => RequestLogger::file(path)?,
+        None => RequestLogger::stdout(),
+    };
 
-    let address = SocketAddr::from(([127, 0, 0, 1], 8080));
+    let routes = axum_test::app(users, mc, logger, &config.static_config(), config.csrf());
 
-    println!("LISTNING on {address}");
+    let listener = TcpListener::bind(config.address).await?;
+    eprintln!("LISTNING on {}", listener.local_addr()?);
 
-    axum::Server::bind(&address)
-        .serve(routes.into_make_service())
-        .await;
-}
+    axum::serve(listener, routes)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
 
-async fn main_response_mapper(response: Response) -> Response {
-    println!("->> {:<12}", "RESPONSE_MAPPER");
-    println!();
+    eprintln!("all connections closed");
 
-    response
+    Ok(())
 }
 
-fn routes_hello() -> Router {
-    Router::new()
-        .route("/hello", get(hello))
-        .route("/hello2/:name", get(hello2))
-}
+/// `axum-test hash-pwd`: reads a password from the first line of stdin and
+/// prints its hash for the `pwd_hash` of a `[[users]]` entry.
+fn hash_pwd() -> Result<(), Box<dyn std::error::Error>> {
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    let pwd = line.trim_end_matches(['\r', '\n']);
+    if pwd.is_empty() {
+        return Err("empty password".into());
+    }
 
-fn routes_static() -> Router {
-    Router::new().nest_service("/", get_service(ServeDir::new("./")))
-}
+    println!("{}", pwd::hash_pwd(pwd, pwd::DEFAULT_COST)?);
 
-#[derive(Debug, Deserialize)]
-struct HelloParams {
-    name: Option<String>,
+    Ok(())
 }
 
-async fn hello(Query(params): Query<HelloParams>) -> impl IntoResponse {
-    let name = params.name.as_deref().unwrap_or("world");
+/// Tickets go to the SQLite database at `database_url` when it is set, e.g.
+/// `sqlite://tickets.db?mode=rwc`, otherwise they only live in memory.
+async fn ticket_store(config: &Config) -> axum_test::Result<Arc<dyn TicketStore>> {
+    let Some(url) = &config.database_url else {
+        return Ok(Arc::new(MemoryTicketStore::new()));
+    };
+
+    let pool = SqlitePoolOptions::new()
+        .connect(url)
+        .await
+        .map_err(|e| Error::StoreFail(e.to_string()))?;
+    let store = SqliteTicketStore::new(pool).await?;
 
-    Html(format!("Hello, {name}!"))
+    Ok(Arc::new(store))
 }
 
-async fn hello2(Path(name): Path<String>) -> impl IntoResponse {
-    Html(format!("Hello, {name}!"))
+/// Resolves on Ctrl-C or SIGTERM. The server then stops accepting
+/// connections and waits for the in-flight requests to finish.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install the Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    eprintln!("draining in-flight requests");
 }
@@ -0,0 +1,51 @@
+use std::sync::{Arc, Mutex};
+
+use axum::async_trait;
+
+use super::{Ticket, TicketForCreate, TicketStore};
+use crate::{Error, Result};
+
+/// Tickets in a vector indexed by id - 1, ids start at 1 like SQLite rowids.
+/// Deleted tickets leave a `None` behind so ids are never reused.
+#[derive(Clone, Default)]
+pub struct MemoryTicketStore {
+    tickets: Arc<Mutex<Vec<Option<Ticket>>>>,
+}
+
+impl MemoryTicketStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TicketStore for MemoryTicketStore {
+    async fn create(&self, cid: u64, ticket_fc: TicketForCreate) -> Result<Ticket> {
+        let mut store = self.tickets.lock().unwrap();
+
+        let ticket = Ticket {
+            id: store.len() as u64 + 1,
+            cid,
+            title: ticket_fc.title,
+        };
+        store.push(Some(ticket.clone()));
+
+        Ok(ticket)
+    }
+
+    async fn list(&self) -> Result<Vec<Ticket>> {
+        let store = self.tickets.lock().unwrap();
+
+        Ok(store.iter().filter_map(|ticket| ticket.clone()).collect())
+    }
+
+    async fn delete(&self, id: u64) -> Result<Ticket> {
+        let mut store = self.tickets.lock().unwrap();
+
+        let index = (id as usize).checked_sub(1);
+        index
+            .and_then(|index| store.get_mut(index))
+            .and_then(|ticket| ticket.take())
+            .ok_or(Error::TicketDeleteFailIdNotFound { id })
+    }
+}
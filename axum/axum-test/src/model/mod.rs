@@ -0,0 +1,131 @@
+//! Model layer: the ticket types, the [`TicketStore`] they are kept in and the
+//! [`ModelController`] the web layer talks to.
+
+mod memory;
+mod sqlite;
+
+use std::sync::Arc;
+
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ctx::Ctx,
+    validate::{FieldErrors, Validate},
+    Result,
+};
+
+const TITLE_MAX_LEN: usize = 200;
+
+pub use self::memory::MemoryTicketStore;
+pub use self::sqlite::SqliteTicketStore;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Ticket {
+    pub id: u64,
+    /// Id of the user who created the ticket.
+    pub cid: u64,
+    pub title: String,
+}
+
+#[derive(Deserialize)]
+pub struct TicketForCreate {
+    pub title: String,
+}
+
+impl Validate for TicketForCreate {
+    fn validate(&self, errors: &mut FieldErrors) {
+        errors.check_len("title", &self.title, TITLE_MAX_LEN);
+    }
+}
+
+#[async_trait]
+pub trait TicketStore: Send + Sync {
+    async fn create(&self, cid: u64, ticket_fc: TicketForCreate) -> Result<Ticket>;
+    async fn list(&self) -> Result<Vec<Ticket>>;
+    /// Fails with `Error::TicketDeleteFailIdNotFound` for unknown ids.
+    async fn delete(&self, id: u64) -> Result<Ticket>;
+}
+
+#[derive(Clone)]
+pub struct ModelController {
+    tickets: Arc<dyn TicketStore>,
+}
+
+impl ModelController {
+    pub fn new(tickets: Arc<dyn TicketStore>) -> Self {
+        Self { tickets }
+    }
+
+    pub async fn create_ticket(&self, ctx: Ctx, ticket_fc: TicketForCreate) -> Result<Ticket> {
+        self.tickets.create(ctx.user_id(), ticket_fc).await
+    }
+
+    pub async fn list_tickets(&self, _ctx: Ctx) -> Result<Vec<Ticket>> {
+        self.tickets.list().await
+    }
+
+    pub async fn delete_ticket(&self, _ctx: Ctx, id: u64) -> Result<Ticket> {
+        self.tickets.delete(id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryTicketStore, ModelController, SqliteTicketStore, TicketForCreate};
+    use crate::{ctx::Ctx, Error};
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::sync::Arc;
+
+    async fn check_crud(mc: ModelController) {
+        let ticket = mc
+            .create_ticket(
+                Ctx::new(7),
+                TicketForCreate {
+                    title: "first".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(ticket.id, 1);
+        assert_eq!(ticket.cid, 7);
+        assert_eq!(ticket.title, "first");
+
+        assert_eq!(
+            mc.list_tickets(Ctx::new(1)).await.unwrap(),
+            vec![ticket.clone()]
+        );
+
+        assert_eq!(
+            mc.delete_ticket(Ctx::new(1), ticket.id).await.unwrap(),
+            ticket
+        );
+        assert!(mc.list_tickets(Ctx::new(1)).await.unwrap().is_empty());
+        assert!(matches!(
+            mc.delete_ticket(Ctx::new(1), ticket.id).await,
+            Err(Error::TicketDeleteFailIdNotFound { id }) if id == ticket.id
+        ));
+        assert!(matches!(
+            mc.delete_ticket(Ctx::new(1), 0).await,
+            Err(Error::TicketDeleteFailIdNotFound { id: 0 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_crud() {
+        check_crud(ModelController::new(Arc::new(MemoryTicketStore::new()))).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_store_crud() {
+        // Every connection to `sqlite::memory:` gets its own database.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let store = SqliteTicketStore::new(pool).await.unwrap();
+
+        check_crud(ModelController::new(Arc::new(store))).await;
+    }
+}
@@ -0,0 +1,78 @@
+use axum::async_trait;
+use sqlx::SqlitePool;
+
+use super::{Ticket, TicketForCreate, TicketStore};
+use crate::{Error, Result};
+
+type TicketRow = (i64, i64, String);
+
+pub struct SqliteTicketStore {
+    pool: SqlitePool,
+}
+
+impl SqliteTicketStore {
+    /// Creates the `ticket` table if it does not exist yet.
+    pub async fn new(pool: SqlitePool) -> Result<Self> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS ticket (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                cid INTEGER NOT NULL,
+                title TEXT NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await
+        .map_err(store_fail)?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl TicketStore for SqliteTicketStore {
+    async fn create(&self, cid: u64, ticket_fc: TicketForCreate) -> Result<Ticket> {
+        let row: TicketRow = sqlx::query_as(
+            "INSERT INTO ticket (cid, title) VALUES (?, ?) RETURNING id, cid, title",
+        )
+        .bind(cid as i64)
+        .bind(ticket_fc.title)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(store_fail)?;
+
+        Ok(into_ticket(row))
+    }
+
+    async fn list(&self) -> Result<Vec<Ticket>> {
+        let rows: Vec<TicketRow> = sqlx::query_as("SELECT id, cid, title FROM ticket ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(store_fail)?;
+
+        Ok(rows.into_iter().map(into_ticket).collect())
+    }
+
+    async fn delete(&self, id: u64) -> Result<Ticket> {
+        let row: Option<TicketRow> =
+            sqlx::query_as("DELETE FROM ticket WHERE id = ? RETURNING id, cid, title")
+                .bind(id as i64)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(store_fail)?;
+
+        row.map(into_ticket)
+            .ok_or(Error::TicketDeleteFailIdNotFound { id })
+    }
+}
+
+fn into_ticket((id, cid, title): TicketRow) -> Ticket {
+    Ticket {
+        id: id as u64,
+        cid: cid as u64,
+        title,
+    }
+}
+
+fn store_fail(e: sqlx::Error) -> Error {
+    Error::StoreFail(e.to_string())
+}
@@ -0,0 +1,123 @@
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::{crypt::pwd, Error, Result};
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: u64,
+    pub username: String,
+    pwd_hash: String,
+}
+
+impl User {
+    pub fn verify_pwd(&self, pwd: &str) -> bool {
+        pwd::verify_pwd(pwd, &self.pwd_hash)
+    }
+}
+
+/// In-memory user store, shared by every clone.
+#[derive(Debug, Clone)]
+pub struct UserStore {
+    users: Arc<RwLock<Vec<User>>>,
+    pwd_cost: u32,
+    /// Checked instead of a real hash when the username is unknown.
+    dummy_hash: Arc<OnceLock<String>>,
+}
+
+impl Default for UserStore {
+    fn default() -> Self {
+        Self::with_pwd_cost(pwd::DEFAULT_COST)
+    }
+}
+
+impl UserStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store hashing with a different bcrypt cost. Tests use
+    /// [`pwd::MIN_COST`], `cfg(test)` does not reach integration tests.
+    pub fn with_pwd_cost(pwd_cost: u32) -> Self {
+        Self {
+            users: Arc::default(),
+            pwd_cost,
+            dummy_hash: Arc::default(),
+        }
+    }
+
+    pub fn create(&self, username: &str, pwd: &str) -> Result<User> {
+        let pwd_hash = pwd::hash_pwd(pwd, self.pwd_cost)?;
+        self.insert(username, pwd_hash)
+    }
+
+    /// Adds a user whose password is already hashed, e.g. one from the
+    /// config file.
+    pub fn insert(&self, username: &str, pwd_hash: String) -> Result<User> {
+        let mut users = self.users.write().unwrap();
+        if users.iter().any(|user| user.username == username) {
+            return Err(Error::UsernameTaken);
+        }
+
+        let user = User {
+            id: users.len() as u64 + 1,
+            username: username.to_string(),
+            pwd_hash,
+        };
+        users.push(user.clone());
+
+        Ok(user)
+    }
+
+    pub fn get(&self, id: u64) -> Option<User> {
+        let users = self.users.read().unwrap();
+        users.iter().find(|user| user.id == id).cloned()
+    }
+
+    pub fn first_by_username(&self, username: &str) -> Option<User> {
+        let users = self.users.read().unwrap();
+        users.iter().find(|user| user.username == username).cloned()
+    }
+
+    /// The user if `pwd` is theirs. An unknown username still costs one
+    /// bcrypt check, so response times do not reveal which usernames exist.
+    pub fn check_login(&self, username: &str, pwd: &str) -> Option<User> {
+        match self.first_by_username(username) {
+            Some(user) => user.verify_pwd(pwd).then_some(user),
+            None => {
+                let dummy_hash = self.dummy_hash.get_or_init(|| {
+                    pwd::hash_pwd("dummy password", self.pwd_cost)
+                        .expect("bcrypt accepts the store's cost")
+                });
+                pwd::verify_pwd(pwd, dummy_hash);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserStore;
+    use crate::crypt::pwd;
+
+    #[test]
+    fn test_check_login() {
+        let users = UserStore::with_pwd_cost(pwd::MIN_COST);
+        let user = users.create("tissy", "secret").unwrap();
+
+        assert_eq!(users.check_login("tissy", "secret").unwrap().id, user.id);
+        assert!(users.check_login("tissy", "wrong").is_none());
+        assert!(users.check_login("nobody", "secret").is_none());
+        assert!(users.dummy_hash.get().is_some());
+    }
+
+    #[test]
+    fn test_insert_hashed() {
+        let users = UserStore::with_pwd_cost(pwd::MIN_COST);
+        let pwd_hash = pwd::hash_pwd("secret", pwd::MIN_COST).unwrap();
+        users.insert("tissy", pwd_hash.clone()).unwrap();
+
+        assert!(users.check_login("tissy", "secret").is_some());
+        assert!(users.insert("tissy", pwd_hash).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use serde::Serialize;
+
+/// One problem with the client's input. `field` is the path of the offending
+/// value, e.g. `title` or `items[2].name`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Collects every [`FieldError`] of a value so the client can fix them all at
+/// once.
+#[derive(Debug, Default)]
+pub struct FieldErrors(Vec<FieldError>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push(FieldError::new(field, message));
+    }
+
+    /// Adds an error when `value` is blank or longer than `max` characters.
+    pub fn check_len(&mut self, field: &str, value: &str, max: usize) {
+        if value.trim().is_empty() {
+            self.add(field, "must not be empty");
+        } else if value.chars().count() > max {
+            self.add(field, format!("must be at most {max} characters"));
+        }
+    }
+
+    pub fn into_inner(self) -> Vec<FieldError> {
+        self.0
+    }
+}
+
+/// Checks run on input after it deserialized, by the `Valid*` extractors in
+/// `web::extract`.
+pub trait Validate {
+    fn validate(&self, errors: &mut FieldErrors);
+}
@@ -0,0 +1,149 @@
+//! Extractors that deserialize and [`Validate`] their input. Every failure,
+//! malformed or invalid, becomes `Error::ValidationFail` with field-level
+//! messages instead of axum's plain-text rejections.
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{
+        path::ErrorKind, rejection::PathRejection, FromRequest, FromRequestParts, Path, Request,
+    },
+    http::{header::CONTENT_TYPE, request::Parts},
+};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    validate::{FieldError, FieldErrors, Validate},
+    Error, Result,
+};
+
+/// A validated `application/json` body.
+#[derive(Debug)]
+pub struct ValidJson<T>(pub T);
+
+/// A validated query string.
+#[derive(Debug)]
+pub struct ValidQuery<T>(pub T);
+
+/// Validated path parameters, deserialized by name into a struct.
+#[derive(Debug)]
+pub struct ValidPath<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self> {
+        let is_json = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        if !is_json {
+            return Err(invalid("body", "expected `Content-Type: application/json`"));
+        }
+
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|rejection| invalid("body", rejection.body_text()))?;
+
+        let deserializer = &mut serde_json::Deserializer::from_slice(&bytes);
+        let value = serde_path_to_error::deserialize(deserializer).map_err(|e| {
+            let path = e.path().to_string();
+            let message = e.into_inner().to_string();
+
+            deserialize_error(path, message)
+        })?;
+
+        validate(value).map(ValidJson)
+    }
+}
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ValidQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self> {
+        let query = parts.uri.query().unwrap_or_default();
+
+        let deserializer =
+            serde_urlencoded::Deserializer::new(form_urlencoded::parse(query.as_bytes()));
+        let value = serde_path_to_error::deserialize(deserializer).map_err(|e| {
+            let path = e.path().to_string();
+            let message = e.into_inner().to_string();
+
+            deserialize_error(path, message)
+        })?;
+
+        validate(value).map(ValidQuery)
+    }
+}
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ValidPath<T>
+where
+    T: DeserializeOwned + Validate + Send,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(path_error)?;
+
+        validate(value).map(ValidPath)
+    }
+}
+
+fn validate<T: Validate>(value: T) -> Result<T> {
+    let mut errors = FieldErrors::default();
+    value.validate(&mut errors);
+
+    let errors = errors.into_inner();
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(Error::ValidationFail(errors))
+    }
+}
+
+/// serde reports missing fields on the parent, so the field name is taken
+/// from the message instead.
+fn deserialize_error(path: String, message: String) -> Error {
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split_once('`'))
+        .map(|(name, _)| name.to_string());
+
+    match missing {
+        Some(name) if path == "." => invalid(name, "is required"),
+        Some(name) => invalid(format!("{path}.{name}"), "is required"),
+        None => invalid(path, message),
+    }
+}
+
+fn path_error(rejection: PathRejection) -> Error {
+    match rejection {
+        PathRejection::FailedToDeserializePathParams(e) => match e.into_kind() {
+            ErrorKind::ParseErrorAtKey {
+                key, expected_type, ..
+            } => invalid(key, format!("expected {expected_type}")),
+            ErrorKind::InvalidUtf8InPathParam { key } => invalid(key, "invalid UTF-8"),
+            kind => invalid("path", kind.to_string()),
+        },
+        rejection => invalid("path", rejection.body_text()),
+    }
+}
+
+fn invalid(field: impl Into<String>, message: impl Into<String>) -> Error {
+    Error::ValidationFail(vec![FieldError::new(field, message)])
+}
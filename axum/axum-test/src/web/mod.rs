@@ -1,3 +1,12 @@
+pub mod extract;
+pub mod mw_auth;
+pub mod mw_csrf;
+pub mod mw_res_map;
+pub mod routes_hello;
 pub mod routes_login;
+pub mod routes_static;
+pub mod routes_tickets;
+pub mod routes_user;
+pub mod routes_ws;
 
-pub const AUTH_TOKEN: &str = "auth-token";
\ No newline at end of file
+pub const AUTH_TOKEN: &str = "auth-token";
@@ -0,0 +1,72 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::request::Parts,
+    middleware::Next,
+    response::Response,
+};
+use tower_cookies::{Cookie, Cookies};
+
+use crate::{
+    crypt::token::{self, Token},
+    ctx::Ctx,
+    users::UserStore,
+    web::AUTH_TOKEN,
+    Error, Result,
+};
+
+/// Rejects the request unless [`mw_ctx_resolver`] produced a valid [`Ctx`].
+pub async fn mw_require_auth(ctx: Result<Ctx>, req: Request, next: Next) -> Result<Response> {
+    ctx?;
+
+    Ok(next.run(req).await)
+}
+
+/// Resolves the auth-token cookie into a `Result<Ctx>` request extension. It
+/// never fails the request itself, that is up to [`mw_require_auth`] or the
+/// [`Ctx`] extractor. An invalid cookie is removed.
+pub async fn mw_ctx_resolver(
+    State(users): State<UserStore>,
+    cookies: Cookies,
+    mut req: Request,
+    next: Next,
+) -> Result<Response> {
+    let result_ctx = match cookies.get(AUTH_TOKEN) {
+        Some(cookie) => resolve_ctx(&users, cookie.value()),
+        None => Err(Error::AuthFailNoAuthTokenCookie),
+    };
+
+    if result_ctx.is_err() && !matches!(result_ctx, Err(Error::AuthFailNoAuthTokenCookie)) {
+        let mut cookie = Cookie::from(AUTH_TOKEN);
+        cookie.set_path("/");
+        cookies.remove(cookie);
+    }
+
+    req.extensions_mut().insert(result_ctx);
+
+    Ok(next.run(req).await)
+}
+
+fn resolve_ctx(users: &UserStore, auth_token: &str) -> Result<Ctx> {
+    let token: Token = auth_token.parse()?;
+    token::validate_web_token(&token)?;
+
+    let user = users
+        .get(token.user_id)
+        .ok_or(Error::AuthFailUserNotFound)?;
+
+    Ok(Ctx::new(user.id))
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Ctx {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self> {
+        parts
+            .extensions
+            .get::<Result<Ctx>>()
+            .ok_or(Error::AuthFailCtxNotInRequestExt)?
+            .clone()
+    }
+}
@@ -0,0 +1,197 @@
+//! CSRF protection for the cookie authenticated routes. Every route group
+//! that takes a [`Csrf`] layer checks the `Origin` of unsafe requests, groups
+//! that opt in with [`Csrf::with_token`] also require the double-submit token:
+//! the `csrf-token` cookie echoed back in the `X-CSRF-Token` header.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{HOST, ORIGIN, REFERER},
+        HeaderMap, Method,
+    },
+    middleware::Next,
+    response::Response,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use tower_cookies::{Cookie, Cookies};
+
+use crate::{Error, Result};
+
+pub const CSRF_TOKEN: &str = "csrf-token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+#[derive(Clone, Debug, Default)]
+pub struct Csrf {
+    /// Origins, e.g. `https://app.example.com`, allowed besides the one the
+    /// request was sent to.
+    allowed_origins: Arc<Vec<String>>,
+    require_token: bool,
+}
+
+impl Csrf {
+    pub fn new(allowed_origins: Vec<String>) -> Self {
+        Self {
+            allowed_origins: Arc::new(allowed_origins),
+            require_token: false,
+        }
+    }
+
+    pub fn with_token(mut self) -> Self {
+        self.require_token = true;
+        self
+    }
+
+    /// The origin check alone, for routes that cannot take the layer.
+    pub fn check_origin(&self, headers: &HeaderMap) -> Result<()> {
+        if origin_allowed(headers, &self.allowed_origins) {
+            Ok(())
+        } else {
+            Err(Error::CsrfFailOriginNotAllowed)
+        }
+    }
+}
+
+/// Passes `GET`, `HEAD` and `OPTIONS` through, rejects other methods from a
+/// foreign origin or, with [`Csrf::with_token`], without the matching token.
+/// Hands out the `csrf-token` cookie when the client has none, even on a
+/// rejected request, so the next attempt can succeed.
+pub async fn mw_csrf(
+    State(csrf): State<Csrf>,
+    cookies: Cookies,
+    req: Request,
+    next: Next,
+) -> Result<Response> {
+    let cookie_token = match cookies.get(CSRF_TOKEN) {
+        Some(cookie) => Some(cookie.value().to_string()),
+        None => {
+            let mut cookie = Cookie::new(CSRF_TOKEN, new_token());
+            cookie.set_path("/");
+            cookies.add(cookie);
+            None
+        }
+    };
+
+    if !is_safe(req.method()) {
+        csrf.check_origin(req.headers())?;
+
+        if csrf.require_token {
+            let header_token = req
+                .headers()
+                .get(CSRF_HEADER)
+                .and_then(|value| value.to_str().ok());
+            match (cookie_token.as_deref(), header_token) {
+                (Some(cookie_token), Some(header_token))
+                    if constant_time_eq(cookie_token.as_bytes(), header_token.as_bytes()) => {}
+                _ => return Err(Error::CsrfFailTokenNotMatching),
+            }
+        }
+    }
+
+    Ok(next.run(req).await)
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Requests without `Origin` or `Referer` do not come from a browser page and
+/// are let through, the token check still applies to them.
+fn origin_allowed(headers: &HeaderMap, allowed_origins: &[String]) -> bool {
+    let origin = match (headers.get(ORIGIN), headers.get(REFERER)) {
+        (Some(origin), _) => origin.to_str().ok().map(str::to_string),
+        (None, Some(referer)) => referer.to_str().ok().and_then(origin_of),
+        (None, None) => return true,
+    };
+    let Some(origin) = origin else {
+        return false;
+    };
+
+    if allowed_origins.contains(&origin) {
+        return true;
+    }
+
+    let host = headers.get(HOST).and_then(|host| host.to_str().ok());
+    let origin_host = origin.split_once("://").map(|(_, host)| host);
+    matches!((origin_host, host), (Some(origin_host), Some(host)) if origin_host == host)
+}
+
+/// `scheme://host[:port]` of a URL.
+fn origin_of(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+
+    Some(format!("{scheme}://{authority}"))
+}
+
+fn new_token() -> String {
+    let mut token = [0; 32];
+    rand::thread_rng().fill_bytes(&mut token);
+
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{origin_allowed, origin_of};
+    use axum::http::{HeaderMap, HeaderValue};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_origin_allowed() {
+        let allowed = vec!["https://app.example.com".to_string()];
+
+        assert!(origin_allowed(&headers(&[]), &allowed));
+        assert!(origin_allowed(
+            &headers(&[("origin", "https://app.example.com")]),
+            &allowed
+        ));
+        assert!(origin_allowed(
+            &headers(&[
+                ("origin", "http://localhost:8080"),
+                ("host", "localhost:8080")
+            ]),
+            &allowed
+        ));
+        assert!(origin_allowed(
+            &headers(&[
+                ("referer", "http://localhost:8080/login?x=1"),
+                ("host", "localhost:8080")
+            ]),
+            &allowed
+        ));
+        assert!(!origin_allowed(
+            &headers(&[
+                ("origin", "https://evil.example"),
+                ("host", "localhost:8080")
+            ]),
+            &allowed
+        ));
+        assert!(!origin_allowed(
+            &headers(&[("origin", "null"), ("host", "localhost:8080")]),
+            &allowed
+        ));
+    }
+
+    #[test]
+    fn test_origin_of() {
+        assert_eq!(
+            origin_of("https://example.com:8443/a/b?c#d").as_deref(),
+            Some("https://example.com:8443")
+        );
+        assert_eq!(origin_of("not a url"), None);
+    }
+}
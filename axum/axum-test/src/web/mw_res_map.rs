@@ -0,0 +1,65 @@
+use axum::{
+    extract::State,
+    http::{Method, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    ctx::Ctx,
+    log::{log_request, RequestLogger},
+    Error,
+};
+
+/// Replaces the body of responses that carry an [`Error`] with
+/// `{"error": {"type": ..., "req_uuid": ...}}` and the matching status, plus
+/// `"fields": [{"field": ..., "message": ...}]` for invalid input. The
+/// uuid ties what the client saw to the request log line written for every
+/// request.
+pub async fn main_response_mapper(
+    State(logger): State<RequestLogger>,
+    ctx: Option<Ctx>,
+    uri: Uri,
+    method: Method,
+    response: Response,
+) -> Response {
+    let uuid = Uuid::new_v4();
+
+    let service_error = response.extensions().get::<Error>().cloned();
+    let client_status_error = service_error
+        .as_ref()
+        .map(|error| error.client_status_and_error());
+
+    let error_response = client_status_error
+        .as_ref()
+        .map(|(status_code, client_error)| {
+            let mut client_error_body = json!({
+                "error": {
+                    "type": client_error.as_ref(),
+                    "req_uuid": uuid.to_string(),
+                }
+            });
+            if let Some(fields) = client_error.fields() {
+                client_error_body["error"]["fields"] = json!(fields);
+            }
+
+            (*status_code, Json(client_error_body)).into_response()
+        });
+
+    let client_error = client_status_error.map(|(_, client_error)| client_error);
+    let response = error_response.unwrap_or(response);
+    log_request(
+        &logger,
+        uuid,
+        &method,
+        &uri,
+        response.status(),
+        ctx.as_ref(),
+        service_error.as_ref(),
+        client_error.as_ref(),
+    );
+
+    response
+}
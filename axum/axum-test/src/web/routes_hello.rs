@@ -0,0 +1,57 @@
+use askama::Template;
+use axum::{response::IntoResponse, routing::get, Router};
+use serde::Deserialize;
+
+use crate::{
+    validate::{FieldErrors, Validate},
+    web::extract::{ValidPath, ValidQuery},
+};
+
+const NAME_MAX_LEN: usize = 64;
+
+pub fn routes() -> Router {
+    Router::new()
+        .route("/hello", get(hello))
+        .route("/hello2/:name", get(hello2))
+}
+
+#[derive(Debug, Deserialize)]
+struct HelloParams {
+    name: Option<String>,
+}
+
+impl Validate for HelloParams {
+    fn validate(&self, errors: &mut FieldErrors) {
+        if let Some(name) = &self.name {
+            errors.check_len("name", name, NAME_MAX_LEN);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HelloPath {
+    name: String,
+}
+
+impl Validate for HelloPath {
+    fn validate(&self, errors: &mut FieldErrors) {
+        errors.check_len("name", &self.name, NAME_MAX_LEN);
+    }
+}
+
+/// `templates/hello.html`, `name` is HTML-escaped when rendered.
+#[derive(Template)]
+#[template(path = "hello.html")]
+struct HelloTemplate {
+    name: String,
+}
+
+async fn hello(ValidQuery(params): ValidQuery<HelloParams>) -> impl IntoResponse {
+    let name = params.name.unwrap_or_else(|| "world".to_string());
+
+    HelloTemplate { name }
+}
+
+async fn hello2(ValidPath(HelloPath { name }): ValidPath<HelloPath>) -> impl IntoResponse {
+    HelloTemplate { name }
+}
@@ -1,20 +1,44 @@
-use axum::{routing::post, Json, Router};
+use axum::{extract::State, routing::post, Json, Router};
 use serde::Deserialize;
 use serde_json::{json, Value};
-use tower_cookies::{Cookie, Cookies};
+use tower_cookies::{cookie::SameSite, Cookie, Cookies};
 
-use crate::{web, Error, Result};
+use crate::{
+    crypt::token,
+    users::UserStore,
+    validate::{FieldErrors, Validate},
+    web::{self, extract::ValidJson},
+    Error, Result,
+};
 
-pub fn routes() -> Router {
-    Router::new().route("/api/login", post(login))
+const USERNAME_MAX_LEN: usize = 64;
+
+pub fn routes(users: UserStore) -> Router {
+    Router::new()
+        .route("/api/login", post(api_login))
+        .with_state(users)
 }
 
-fn login(cookies: Cookies, payload: Json<LoginPayload>) -> Result<Json<Value>> {
-    if payload.username != "tissy" || payload.password != "secret" {
-        return Err(Error::LoginFail);
-    }
+/// Unknown users and wrong passwords fail the same way, so the response does
+/// not reveal which usernames exist.
+async fn api_login(
+    State(users): State<UserStore>,
+    cookies: Cookies,
+    ValidJson(payload): ValidJson<LoginPayload>,
+) -> Result<Json<Value>> {
+    let user = users
+        .check_login(&payload.username, &payload.password)
+        .ok_or(Error::LoginFail)?;
 
-    cookies.add(Cookie::new(web::AUTH_TOKEN, "user-1.exp.sign"));
+    // Browsers accept `Secure` cookies from http://localhost, so it does not
+    // get in the way of local development.
+    let token = token::generate_web_token(user.id);
+    let mut cookie = Cookie::new(web::AUTH_TOKEN, token.to_string());
+    cookie.set_path("/");
+    cookie.set_http_only(true);
+    cookie.set_secure(true);
+    cookie.set_same_site(SameSite::Strict);
+    cookies.add(cookie);
 
     let body = Json(json!({
         "result": {
@@ -30,3 +54,12 @@ struct LoginPayload {
     username: String,
     password: String,
 }
+
+impl Validate for LoginPayload {
+    fn validate(&self, errors: &mut FieldErrors) {
+        errors.check_len("username", &self.username, USERNAME_MAX_LEN);
+        if self.password.is_empty() {
+            errors.add("password", "must not be empty");
+        }
+    }
+}
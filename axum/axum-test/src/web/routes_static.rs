@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::Request,
+    http::{
+        header::{
+            CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, ETAG, IF_NONE_MATCH, LAST_MODIFIED,
+            VARY,
+        },
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get_service, MethodRouter},
+    Router,
+};
+use sha2::{Digest, Sha256};
+use tower_http::services::{ServeDir, ServeFile};
+
+/// Where static files come from and whether unknown paths fall back to
+/// `index.html`, for single-page apps that route on the client.
+#[derive(Clone, Debug)]
+pub struct StaticConfig {
+    pub root: PathBuf,
+    pub spa_fallback: bool,
+}
+
+impl StaticConfig {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            spa_fallback: false,
+        }
+    }
+}
+
+pub fn routes(config: &StaticConfig) -> Router {
+    Router::new().fallback_service(serve_dir(config))
+}
+
+/// Serves `config.root`, preferring `.br` and `.gz` siblings when the client
+/// accepts them. Directories only ever serve their `index.html`, they are
+/// never listed.
+fn serve_dir(config: &StaticConfig) -> MethodRouter {
+    let serve_dir = ServeDir::new(&config.root)
+        .precompressed_br()
+        .precompressed_gzip();
+
+    let service = if config.spa_fallback {
+        let index = ServeFile::new(config.root.join("index.html"))
+            .precompressed_br()
+            .precompressed_gzip();
+        get_service(serve_dir.fallback(index))
+    } else {
+        get_service(serve_dir)
+    };
+
+    service.layer(middleware::from_fn(mw_cache_headers))
+}
+
+/// `ServeDir` already answers `If-Modified-Since` from `Last-Modified`, this
+/// adds a weak `ETag` and answers `If-None-Match` with a 304.
+async fn mw_cache_headers(req: Request, next: Next) -> Response {
+    let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
+
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let Some(etag) = etag(response.headers()) else {
+        return response;
+    };
+
+    if if_none_match.is_some_and(|value| etag_matches(&value, &etag)) {
+        let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+        let headers = not_modified.headers_mut();
+        for name in [LAST_MODIFIED, VARY] {
+            if let Some(value) = response.headers().get(&name) {
+                headers.insert(name, value.clone());
+            }
+        }
+        headers.insert(ETAG, etag);
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+        return not_modified;
+    }
+
+    let headers = response.headers_mut();
+    headers.insert(ETAG, etag);
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+    response
+}
+
+/// Derived from the size, modification time and encoding of the file that was
+/// served, so it changes whenever the file does without reading it again.
+fn etag(headers: &HeaderMap) -> Option<HeaderValue> {
+    let last_modified = headers.get(LAST_MODIFIED)?;
+    let content_length = headers.get(CONTENT_LENGTH)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(last_modified.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(content_length.as_bytes());
+    if let Some(encoding) = headers.get(CONTENT_ENCODING) {
+        hasher.update(b"\0");
+        hasher.update(encoding.as_bytes());
+    }
+    let digest = hasher.finalize();
+
+    let hex: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    HeaderValue::from_str(&format!("W/\"{hex}\"")).ok()
+}
+
+/// Weak comparison as `If-None-Match` requires, `W/` prefixes are ignored.
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+    let etag = etag.to_str().unwrap_or_default().trim_start_matches("W/");
+
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
@@ -0,0 +1,56 @@
+use axum::{
+    extract::State,
+    routing::{delete, post},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::{
+    ctx::Ctx,
+    model::{ModelController, Ticket, TicketForCreate},
+    validate::{FieldErrors, Validate},
+    web::extract::{ValidJson, ValidPath},
+    Result,
+};
+
+pub fn routes(mc: ModelController) -> Router {
+    Router::new()
+        .route("/tickets", post(create_ticket).get(list_tickets))
+        .route("/tickets/:id", delete(delete_ticket))
+        .with_state(mc)
+}
+
+#[derive(Deserialize)]
+struct TicketPath {
+    id: u64,
+}
+
+impl Validate for TicketPath {
+    fn validate(&self, _errors: &mut FieldErrors) {}
+}
+
+async fn create_ticket(
+    State(mc): State<ModelController>,
+    ctx: Ctx,
+    ValidJson(ticket_fc): ValidJson<TicketForCreate>,
+) -> Result<Json<Ticket>> {
+    let ticket = mc.create_ticket(ctx, ticket_fc).await?;
+
+    Ok(Json(ticket))
+}
+
+async fn list_tickets(State(mc): State<ModelController>, ctx: Ctx) -> Result<Json<Vec<Ticket>>> {
+    let tickets = mc.list_tickets(ctx).await?;
+
+    Ok(Json(tickets))
+}
+
+async fn delete_ticket(
+    State(mc): State<ModelController>,
+    ctx: Ctx,
+    ValidPath(TicketPath { id }): ValidPath<TicketPath>,
+) -> Result<Json<Ticket>> {
+    let ticket = mc.delete_ticket(ctx, id).await?;
+
+    Ok(Json(ticket))
+}
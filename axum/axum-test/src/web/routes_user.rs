@@ -0,0 +1,21 @@
+use axum::{extract::State, routing::get, Json, Router};
+use serde_json::{json, Value};
+
+use crate::{ctx::Ctx, users::UserStore, Error, Result};
+
+pub fn routes(users: UserStore) -> Router {
+    Router::new().route("/me", get(api_me)).with_state(users)
+}
+
+async fn api_me(State(users): State<UserStore>, ctx: Ctx) -> Result<Json<Value>> {
+    let user = users
+        .get(ctx.user_id())
+        .ok_or(Error::AuthFailUserNotFound)?;
+
+    Ok(Json(json!({
+        "result": {
+            "id": user.id,
+            "username": user.username
+        }
+    })))
+}
@@ -0,0 +1,99 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::HeaderMap,
+    response::Response,
+    routing::get,
+    Router,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{chat::ChatRoom, ctx::Ctx, users::UserStore, web::mw_csrf::Csrf, Error, Result};
+
+/// Longer messages are dropped.
+const MESSAGE_MAX_LEN: usize = 1000;
+
+#[derive(Clone)]
+struct WsState {
+    users: UserStore,
+    room: ChatRoom,
+    csrf: Csrf,
+}
+
+pub fn routes(users: UserStore, room: ChatRoom, csrf: Csrf) -> Router {
+    Router::new()
+        .route("/ws", get(ws_handler))
+        .with_state(WsState { users, room, csrf })
+}
+
+/// Upgrades logged in users into the chat room. Browsers send the auth cookie
+/// with cross-site WebSocket handshakes too, so the origin is checked here.
+async fn ws_handler(
+    State(state): State<WsState>,
+    ctx: Ctx,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response> {
+    state.csrf.check_origin(&headers)?;
+    let user = state
+        .users
+        .get(ctx.user_id())
+        .ok_or(Error::AuthFailUserNotFound)?;
+
+    Ok(ws.on_upgrade(move |socket| chat(socket, state.room, user.username)))
+}
+
+/// Sends the history, then forwards room events to the client and the
+/// client's text messages to the room until either side goes away.
+async fn chat(socket: WebSocket, room: ChatRoom, username: String) {
+    let (mut sink, mut stream) = socket.split();
+    let (history, mut events) = room.join(&username);
+
+    let history = json!({ "type": "history", "messages": history }).to_string();
+    if sink.send(Message::Text(history)).await.is_err() {
+        room.leave(&username);
+        return;
+    }
+
+    let mut send_task = tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            let text = serde_json::to_string(&event).expect("chat events serialize");
+            if sink.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let recv_room = room.clone();
+    let recv_username = username.clone();
+    let mut recv_task = tokio::spawn(async move {
+        while let Some(Ok(message)) = stream.next().await {
+            match message {
+                Message::Text(text) => {
+                    let text = text.trim();
+                    if !text.is_empty() && text.chars().count() <= MESSAGE_MAX_LEN {
+                        recv_room.post(&recv_username, text.to_string());
+                    }
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+    });
+
+    tokio::select! {
+        _ = &mut send_task => recv_task.abort(),
+        _ = &mut recv_task => send_task.abort(),
+    }
+
+    room.leave(&username);
+}
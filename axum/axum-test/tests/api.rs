@@ -0,0 +1,688 @@
+use anyhow::Result;
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{
+    tungstenite::{self, client::IntoClientRequest, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+use tower::ServiceExt;
+
+use axum_test::{
+    crypt::pwd,
+    log::RequestLogger,
+    model::{MemoryTicketStore, ModelController},
+    users::UserStore,
+    web::{
+        mw_csrf::{Csrf, CSRF_HEADER},
+        routes_static::StaticConfig,
+    },
+};
+
+const STATIC_ROOT: &str = "tests/static";
+const ALLOWED_ORIGIN: &str = "http://localhost:3000";
+
+/// The cookies of a logged in client and the CSRF token it echoes back.
+struct Session {
+    cookie: String,
+    csrf: String,
+}
+
+fn app() -> Router {
+    app_with_static(&StaticConfig::new(STATIC_ROOT))
+}
+
+fn app_with_static(static_config: &StaticConfig) -> Router {
+    let users = UserStore::with_pwd_cost(pwd::MIN_COST);
+    users.create("tissy", "secret").unwrap();
+    let mc = ModelController::new(Arc::new(MemoryTicketStore::new()));
+
+    axum_test::app(
+        users,
+        mc,
+        RequestLogger::stdout(),
+        static_config,
+        Csrf::new(vec![ALLOWED_ORIGIN.to_string()]),
+    )
+}
+
+async fn send(app: &Router, req: Request<Body>) -> Response {
+    app.clone().oneshot(req).await.unwrap()
+}
+
+fn request(method: &str, uri: &str, session: Option<&Session>) -> axum::http::request::Builder {
+    let mut req = Request::builder().method(method).uri(uri);
+    if let Some(session) = session {
+        req = req
+            .header(header::COOKIE, session.cookie.as_str())
+            .header(CSRF_HEADER, session.csrf.as_str());
+    }
+    req
+}
+
+fn get(uri: &str, session: Option<&Session>) -> Request<Body> {
+    request("GET", uri, session).body(Body::empty()).unwrap()
+}
+
+fn delete(uri: &str, session: Option<&Session>) -> Request<Body> {
+    request("DELETE", uri, session).body(Body::empty()).unwrap()
+}
+
+fn json_request(method: &str, uri: &str, session: Option<&Session>, body: Value) -> Request<Body> {
+    request(method, uri, session)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// The `name=value` pair of the cookie `name` set by `response`.
+fn set_cookie(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.starts_with(&format!("{name}=")))
+        .map(|value| value.split(';').next().unwrap().to_string())
+}
+
+async fn body_string(response: Response) -> String {
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+async fn body_json(response: Response) -> Value {
+    serde_json::from_str(&body_string(response).await).unwrap()
+}
+
+/// Logs in as the seeded user.
+async fn login(app: &Router) -> Session {
+    let response = send(
+        app,
+        json_request(
+            "POST",
+            "/api/login",
+            None,
+            json!({ "username": "tissy", "password": "secret" }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let auth_cookie = response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .find(|value| value.starts_with("auth-token="))
+        .unwrap();
+    for attribute in ["HttpOnly", "Secure", "SameSite=Strict"] {
+        assert!(auth_cookie.contains(attribute), "{auth_cookie}");
+    }
+
+    let auth = set_cookie(&response, "auth-token").unwrap();
+    let csrf = set_cookie(&response, "csrf-token").unwrap();
+
+    Session {
+        cookie: format!("{auth}; {csrf}"),
+        csrf: csrf.split_once('=').unwrap().1.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_hello() -> Result<()> {
+    let app = app();
+
+    let response = send(&app, get("/hello?name=Jen", None)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[header::CONTENT_TYPE]
+        .to_str()?
+        .starts_with("text/html"));
+    let body = body_string(response).await;
+    assert!(body.contains("<title>Hello, Jen</title>"));
+    assert!(body.contains("<h1>Hello, Jen!</h1>"));
+
+    let response = send(&app, get("/hello", None)).await;
+    assert!(body_string(response)
+        .await
+        .contains("<h1>Hello, world!</h1>"));
+
+    let response = send(&app, get("/hello2/Mike", None)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_string(response)
+        .await
+        .contains("<h1>Hello, Mike!</h1>"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_hello_escapes_name() -> Result<()> {
+    let app = app();
+
+    let response = send(
+        &app,
+        get("/hello?name=%3Cscript%3Ealert(1)%3C%2Fscript%3E", None),
+    )
+    .await;
+    let body = body_string(response).await;
+    assert!(!body.contains("<script>"));
+    assert!(body.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_login() -> Result<()> {
+    let app = app();
+
+    let response = send(
+        &app,
+        json_request(
+            "POST",
+            "/api/login",
+            None,
+            json!({ "username": "tissy", "password": "secret" }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key(header::SET_COOKIE));
+    assert_eq!(
+        body_json(response).await,
+        json!({ "result": { "success": true } })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_login_fail() -> Result<()> {
+    let app = app();
+
+    let response = send(
+        &app,
+        json_request(
+            "POST",
+            "/api/login",
+            None,
+            json!({ "username": "tissy", "password": "wrong" }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(set_cookie(&response, "auth-token").is_none());
+
+    let body = body_json(response).await;
+    assert_eq!(body["error"]["type"], "LOGIN_FAIL");
+    assert!(body["error"]["req_uuid"].is_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_api_requires_auth() -> Result<()> {
+    let app = app();
+
+    let response = send(&app, get("/api/me", None)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(body_json(response).await["error"]["type"], "NO_AUTH");
+
+    let garbage = Session {
+        cookie: "auth-token=garbage".to_string(),
+        csrf: String::new(),
+    };
+    let response = send(&app, get("/api/me", Some(&garbage))).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_me() -> Result<()> {
+    let app = app();
+    let session = login(&app).await;
+
+    let response = send(&app, get("/api/me", Some(&session))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        body_json(response).await,
+        json!({ "result": { "id": 1, "username": "tissy" } })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tickets() -> Result<()> {
+    let app = app();
+    let session = login(&app).await;
+
+    let response = send(
+        &app,
+        json_request(
+            "POST",
+            "/api/tickets",
+            Some(&session),
+            json!({ "title": "Ticket AAA" }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let ticket = body_json(response).await;
+    assert_eq!(ticket["id"], 1);
+    assert_eq!(ticket["title"], "Ticket AAA");
+    assert_eq!(ticket["cid"], 1);
+
+    let response = send(&app, get("/api/tickets", Some(&session))).await;
+    assert_eq!(body_json(response).await, json!([ticket]));
+
+    let uri = format!("/api/tickets/{}", ticket["id"]);
+    let response = send(&app, delete(&uri, Some(&session))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = send(&app, delete(&uri, Some(&session))).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(body_json(response).await["error"]["type"], "NOT_FOUND");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_static() -> Result<()> {
+    let app = app();
+
+    let response = send(&app, get("/assets/app.js", None)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key(header::LAST_MODIFIED));
+    assert_eq!(body_string(response).await, "console.log(\"app\");\n");
+
+    let response = send(&app, get("/", None)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_string(response).await.contains("spa index"));
+
+    // Only the static root is served, not the working directory.
+    let response = send(&app, get("/Cargo.toml", None)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = send(&app, get("/../Cargo.toml", None)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // No directory listings.
+    let response = send(&app, get("/assets/", None)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_static_precompressed() -> Result<()> {
+    let app = app();
+
+    let req = Request::get("/assets/app.js")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())?;
+    let response = send(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    assert_eq!(response.headers()[header::VARY], "accept-encoding");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_static_etag() -> Result<()> {
+    let app = app();
+
+    let response = send(&app, get("/assets/app.js", None)).await;
+    let etag = response.headers()[header::ETAG].clone();
+    assert!(etag.to_str()?.starts_with("W/\""));
+
+    let req = Request::get("/assets/app.js")
+        .header(header::IF_NONE_MATCH, etag.clone())
+        .body(Body::empty())?;
+    let response = send(&app, req).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag);
+    assert!(body_string(response).await.is_empty());
+
+    let req = Request::get("/assets/app.js")
+        .header(header::IF_NONE_MATCH, "W/\"stale\"")
+        .body(Body::empty())?;
+    let response = send(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_static_spa_fallback() -> Result<()> {
+    let response = send(&app(), get("/some/client/route", None)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let mut static_config = StaticConfig::new(STATIC_ROOT);
+    static_config.spa_fallback = true;
+    let app = app_with_static(&static_config);
+
+    let response = send(&app, get("/some/client/route", None)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_string(response).await.contains("spa index"));
+
+    let response = send(&app, get("/assets/app.js", None)).await;
+    assert_eq!(body_string(response).await, "console.log(\"app\");\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_json_input() -> Result<()> {
+    let app = app();
+
+    let response = send(
+        &app,
+        json_request("POST", "/api/login", None, json!({ "username": "tissy" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = body_json(response).await;
+    assert_eq!(body["error"]["type"], "INVALID_INPUT");
+    assert_eq!(
+        body["error"]["fields"],
+        json!([{ "field": "password", "message": "is required" }])
+    );
+
+    let response = send(
+        &app,
+        json_request(
+            "POST",
+            "/api/login",
+            None,
+            json!({ "username": 42, "password": "secret" }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body_json(response).await["error"]["fields"][0]["field"],
+        "username"
+    );
+
+    let req = Request::post("/api/login")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{not json"))?;
+    let response = send(&app, req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(body_json(response).await["error"]["type"], "INVALID_INPUT");
+
+    let req = Request::post("/api/login").body(Body::from("username=tissy"))?;
+    let response = send(&app, req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body_json(response).await["error"]["fields"][0]["field"],
+        "body"
+    );
+
+    let response = send(
+        &app,
+        json_request(
+            "POST",
+            "/api/login",
+            None,
+            json!({ "username": " ", "password": "" }),
+        ),
+    )
+    .await;
+    assert_eq!(
+        body_json(response).await["error"]["fields"],
+        json!([
+            { "field": "username", "message": "must not be empty" },
+            { "field": "password", "message": "must not be empty" },
+        ])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_query_and_path_input() -> Result<()> {
+    let app = app();
+
+    let long_name = "x".repeat(65);
+    let response = send(&app, get(&format!("/hello?name={long_name}"), None)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body_json(response).await["error"]["fields"],
+        json!([{ "field": "name", "message": "must be at most 64 characters" }])
+    );
+
+    let response = send(&app, get(&format!("/hello2/{long_name}"), None)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let session = login(&app).await;
+    let response = send(&app, delete("/api/tickets/abc", Some(&session))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body_json(response).await["error"]["fields"],
+        json!([{ "field": "id", "message": "expected u64" }])
+    );
+
+    let response = send(
+        &app,
+        json_request(
+            "POST",
+            "/api/tickets",
+            Some(&session),
+            json!({ "title": "" }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body_json(response).await["error"]["fields"][0]["field"],
+        "title"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_csrf_token_required() -> Result<()> {
+    let app = app();
+    let session = login(&app).await;
+    let body = json!({ "title": "Ticket AAA" });
+
+    let mut no_token = request("POST", "/api/tickets", None)
+        .header(header::COOKIE, session.cookie.as_str())
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))?;
+    let response = send(&app, no_token).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(body_json(response).await["error"]["type"], "CSRF_FAIL");
+
+    no_token = request("POST", "/api/tickets", None)
+        .header(header::COOKIE, session.cookie.as_str())
+        .header(CSRF_HEADER, "wrong")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))?;
+    let response = send(&app, no_token).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Safe methods need no token.
+    let response = send(
+        &app,
+        request("GET", "/api/tickets", None)
+            .header(header::COOKIE, session.cookie.as_str())
+            .body(Body::empty())?,
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = send(
+        &app,
+        json_request("POST", "/api/tickets", Some(&session), body),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_csrf_token_issued_when_missing() -> Result<()> {
+    let app = app();
+    let session = login(&app).await;
+    let auth_only = session.cookie.split("; ").next().unwrap();
+
+    let response = send(
+        &app,
+        request("GET", "/api/me", None)
+            .header(header::COOKIE, auth_only)
+            .body(Body::empty())?,
+    )
+    .await;
+    assert!(set_cookie(&response, "csrf-token").is_some());
+
+    let response = send(&app, get("/api/me", Some(&session))).await;
+    assert!(set_cookie(&response, "csrf-token").is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_csrf_origin_check() -> Result<()> {
+    let app = app();
+    let login_request = |origin: &str| {
+        Request::post("/api/login")
+            .header(header::HOST, "localhost:8080")
+            .header(header::ORIGIN, origin)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({ "username": "tissy", "password": "secret" }).to_string(),
+            ))
+            .unwrap()
+    };
+
+    let response = send(&app, login_request("https://evil.example")).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(body_json(response).await["error"]["type"], "CSRF_FAIL");
+
+    let response = send(&app, login_request("http://localhost:8080")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = send(&app, login_request(ALLOWED_ORIGIN)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ws_requires_auth() -> Result<()> {
+    let req = Request::get("/ws")
+        .header(header::CONNECTION, "upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_VERSION, "13")
+        .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+        .body(Body::empty())?;
+
+    let response = send(&app(), req).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(body_json(response).await["error"]["type"], "NO_AUTH");
+
+    Ok(())
+}
+
+type WsClient = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Serves `app` on a random local port, returns its address.
+async fn serve(app: Router) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    Ok(address)
+}
+
+/// Opens `/ws` on `address` as `session`, sending `origin`.
+async fn ws_connect(
+    address: &str,
+    session: &Session,
+    origin: &str,
+) -> tungstenite::Result<WsClient> {
+    let mut req = format!("ws://{address}/ws").into_client_request()?;
+    req.headers_mut()
+        .insert(header::COOKIE, session.cookie.parse().unwrap());
+    req.headers_mut()
+        .insert(header::ORIGIN, origin.parse().unwrap());
+
+    let (ws, _) = tokio_tungstenite::connect_async(req).await?;
+    Ok(ws)
+}
+
+/// The next text message, parsed as JSON.
+async fn ws_next(ws: &mut WsClient) -> Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), ws.next())
+            .await
+            .expect("timed out waiting for a websocket message")
+            .expect("websocket closed")
+            .unwrap();
+        if let Message::Text(text) = message {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_ws_chat() -> Result<()> {
+    let app = app();
+    let session = login(&app).await;
+    let address = serve(app).await?;
+
+    match ws_connect(&address, &session, "http://evil.example.com").await {
+        Err(tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::FORBIDDEN)
+        }
+        other => panic!("cross-origin handshake not rejected: {other:?}"),
+    }
+
+    let mut first = ws_connect(&address, &session, ALLOWED_ORIGIN).await?;
+    assert_eq!(
+        ws_next(&mut first).await,
+        json!({ "type": "history", "messages": [] })
+    );
+    assert_eq!(
+        ws_next(&mut first).await,
+        json!({ "type": "join", "username": "tissy" })
+    );
+
+    first.send(Message::Text("hello".into())).await?;
+    let hello = json!({ "type": "message", "username": "tissy", "text": "hello" });
+    assert_eq!(ws_next(&mut first).await, hello);
+
+    // A later member gets the messages posted before it joined.
+    let mut second = ws_connect(&address, &session, ALLOWED_ORIGIN).await?;
+    assert_eq!(
+        ws_next(&mut second).await,
+        json!({ "type": "history", "messages": [hello] })
+    );
+    assert_eq!(
+        ws_next(&mut first).await,
+        json!({ "type": "join", "username": "tissy" })
+    );
+
+    second.close(None).await?;
+    assert_eq!(
+        ws_next(&mut first).await,
+        json!({ "type": "leave", "username": "tissy" })
+    );
+
+    Ok(())
+}
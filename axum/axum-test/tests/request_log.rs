@@ -0,0 +1,68 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Command, Stdio},
+};
+
+use anyhow::Result;
+use serde_json::Value;
+
+/// Sends `request` and reads the response until the server closes the
+/// connection.
+fn send(address: &str, request: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(address)?;
+    stream.write_all(request.as_bytes())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+#[test]
+fn test_stdout_request_log_is_json_lines() -> Result<()> {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_axum-test"))
+        .env("CONFIG_FILE", "/dev/null")
+        .env("ADDRESS", "127.0.0.1:0")
+        .env_remove("REQUEST_LOG_FILE")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut stderr = BufReader::new(server.stderr.take().unwrap());
+    let mut line = String::new();
+    let address = loop {
+        line.clear();
+        if stderr.read_line(&mut line)? == 0 {
+            server.kill()?;
+            anyhow::bail!("server exited before listening");
+        }
+        if let Some(address) = line.trim().strip_prefix("LISTNING on ") {
+            break address.to_string();
+        }
+    };
+
+    let requests = [
+        "GET /hello?name=tissy HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        "GET /api/tickets HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        "GET /hello?name=xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    ];
+    for request in requests {
+        send(&address, request)?;
+    }
+
+    server.kill()?;
+    let mut stdout = String::new();
+    server.stdout.take().unwrap().read_to_string(&mut stdout)?;
+    server.wait()?;
+
+    let lines: Vec<Value> = stdout
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<serde_json::Result<_>>()?;
+    assert_eq!(lines.len(), requests.len(), "stdout: {stdout:?}");
+    assert_eq!(lines[0]["http_uri"], "/hello?name=tissy");
+    assert_eq!(lines[1]["http_status"], 403);
+    assert_eq!(lines[1]["client_error_type"], "NO_AUTH");
+    assert_eq!(lines[2]["client_error_type"], "INVALID_INPUT");
+
+    Ok(())
+}
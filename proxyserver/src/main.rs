@@ -1,50 +1,391 @@
-use std::net::{SocketAddr, ToSocketAddrs};
-use hyper::{Body, Client, Request, Response, Server};
+use std::convert::Infallible;
+use std::env;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::process;
+use std::sync::Arc;
+
+use hyper::client::HttpConnector;
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::http::uri::{Parts, PathAndQuery};
 use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
-use tokio::net::TcpListener;
+use hyper::{Body, Client, Request, Response, Server, StatusCode, Uri, Version};
 use tokio::sync::oneshot;
 
-async fn forward_request(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
-    // Replace the following URL with the destination URL you want to proxy to
-    let url = "http://example.com".parse().unwrap();
-    let mut dest_req = Request::new(req.body());
-    *dest_req.uri_mut() = url;
+const USAGE: &str = "usage: proxyserver [--listen ADDR] --upstream URL
+
+  --listen ADDR    address to listen on (default 0.0.0.0:8080)
+  --upstream URL   where requests are forwarded, e.g. http://127.0.0.1:3000
+                   (falls back to the PROXY_UPSTREAM environment variable)";
+
+#[derive(Debug)]
+struct Config {
+    listen: SocketAddr,
+    upstream: Uri,
+}
+
+impl Config {
+    fn from_args() -> Result<Config, String> {
+        let mut listen = String::from("0.0.0.0:8080");
+        let mut upstream = env::var("PROXY_UPSTREAM").ok();
 
-    // Add headers from the original request to the destination request
-    for (name, value) in req.headers() {
-        dest_req.headers_mut().insert(name, value.clone());
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--listen" => listen = args.next().ok_or("--listen needs a value")?,
+                "--upstream" => upstream = Some(args.next().ok_or("--upstream needs a value")?),
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
+        }
+
+        let listen = listen
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| format!("invalid listen address `{}`", listen))?;
+        let upstream = upstream.ok_or("no upstream, pass --upstream or set PROXY_UPSTREAM")?;
+        let upstream = parse_upstream(&upstream)?;
+
+        Ok(Config { listen, upstream })
     }
+}
 
-    // Send the destination request and return the response
-    let client = Client::new();
-    client.request(dest_req).await
+/// The upstream must be a plain `http://host[:port][/base]` URL, the request
+/// path and query are appended to its base path.
+fn parse_upstream(s: &str) -> Result<Uri, String> {
+    let uri: Uri = s
+        .parse()
+        .map_err(|e| format!("invalid upstream `{}`: {}", s, e))?;
+
+    if uri.scheme_str() != Some("http") || uri.authority().is_none() {
+        return Err(format!(
+            "upstream `{}` must be an http:// URL with a host",
+            s
+        ));
+    }
+    if uri.query().is_some() {
+        return Err(format!("upstream `{}` must not have a query", s));
+    }
+
+    Ok(uri)
 }
 
-async fn handle_request(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
-    forward_request(req).await
+/// Maps the incoming request target onto the upstream, keeping its path and
+/// query: `/a/b?c` against `http://backend/base` becomes
+/// `http://backend/base/a/b?c`.
+fn upstream_uri(upstream: &Uri, target: &Uri) -> Result<Uri, hyper::http::Error> {
+    let base = upstream.path().trim_end_matches('/');
+    let path_and_query = target
+        .path_and_query()
+        .map(PathAndQuery::as_str)
+        .unwrap_or("/");
+
+    let mut parts = Parts::default();
+    parts.scheme = upstream.scheme().cloned();
+    parts.authority = upstream.authority().cloned();
+    parts.path_and_query = Some(format!("{}{}", base, path_and_query).parse()?);
+
+    Ok(Uri::from_parts(parts)?)
 }
 
-async fn run_server(addr: SocketAddr, shutdown: oneshot::Receiver<()>) {
-    let make_svc = make_service_fn(|socket: &AddrStream| async move {
-        Ok::<_, hyper::Error>(service_fn(handle_request))
-    });
-    let server = Server::bind(&addr).serve(make_svc);
-    let graceful = server.with_graceful_shutdown(async {
-        shutdown.await.ok();
+/// Headers that only describe the connection to the immediate peer (RFC 7230
+/// section 6.1), a proxy must not pass them on. `Proxy-Connection` is the
+/// non-standard one some old clients still send.
+const HOP_BY_HOP: [HeaderName; 9] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    HeaderName::from_static("proxy-connection"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Removes the hop-by-hop headers, including any the sender listed in its
+/// `Connection` header.
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect();
+
+    for name in listed.iter().chain(HOP_BY_HOP.iter()) {
+        headers.remove(name);
+    }
+}
+
+/// Tells the upstream who the client is: the client address is appended to
+/// `X-Forwarded-For` and `Forwarded`, the host and scheme the client asked for
+/// go into `X-Forwarded-Host` and `X-Forwarded-Proto`.
+fn add_forwarded_headers(headers: &mut HeaderMap, client: IpAddr, host: Option<&HeaderValue>) {
+    let forwarded_for = match headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()) {
+        Some(previous) => format!("{}, {}", previous, client),
+        None => client.to_string(),
+    };
+    insert_header(headers, "x-forwarded-for", &forwarded_for);
+    insert_header(headers, "x-forwarded-proto", "http");
+
+    let host = host.and_then(|value| value.to_str().ok());
+    match host {
+        Some(host) => insert_header(headers, "x-forwarded-host", host),
+        None => {
+            headers.remove("x-forwarded-host");
+        }
+    }
+
+    // IPv6 addresses are quoted and bracketed in `Forwarded` (RFC 7239).
+    let node = match client {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("\"[{}]\"", ip),
+    };
+    let mut element = format!("for={};proto=http", node);
+    if let Some(host) = host {
+        element.push_str(&format!(";host={}", forwarded_value(host)));
+    }
+    let forwarded = match headers.get(header::FORWARDED).and_then(|v| v.to_str().ok()) {
+        Some(previous) => format!("{}, {}", previous, element),
+        None => element,
+    };
+    insert_header(headers, header::FORWARDED.as_str(), &forwarded);
+}
+
+/// A `Forwarded` parameter value is a token or a quoted string.
+fn forwarded_value(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if is_token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+fn insert_header(headers: &mut HeaderMap, name: &'static str, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+/// Sends the request on to the upstream with its method, end-to-end headers
+/// and body. The body is streamed through, never buffered. `Host` becomes the
+/// upstream's, the original one is passed on in the forwarding headers.
+async fn forward_request(
+    client: &Client<HttpConnector>,
+    upstream: &Uri,
+    remote_addr: SocketAddr,
+    req: Request<Body>,
+) -> Result<Response<Body>, String> {
+    let (mut parts, body) = req.into_parts();
+
+    // HTTP/2 clients send the host in the request target, not in `Host`.
+    let original_host = parts.headers.remove(header::HOST).or_else(|| {
+        let authority = parts.uri.authority()?;
+        HeaderValue::from_str(authority.as_str()).ok()
     });
-    if let Err(e) = graceful.await {
-        eprintln!("server error: {}", e);
+    parts.uri = upstream_uri(upstream, &parts.uri).map_err(|e| e.to_string())?;
+    // The client only speaks HTTP/1 to the upstream, whatever the inbound
+    // connection was (an h2c request would otherwise be rejected).
+    parts.version = Version::HTTP_11;
+
+    strip_hop_by_hop(&mut parts.headers);
+    add_forwarded_headers(&mut parts.headers, remote_addr.ip(), original_host.as_ref());
+    if let Some(authority) = upstream.authority() {
+        insert_header(
+            &mut parts.headers,
+            header::HOST.as_str(),
+            authority.as_str(),
+        );
+    }
+
+    let dest_req = Request::from_parts(parts, body);
+    let mut response = client.request(dest_req).await.map_err(|e| e.to_string())?;
+    strip_hop_by_hop(response.headers_mut());
+
+    Ok(response)
+}
+
+async fn handle_request(
+    client: Client<HttpConnector>,
+    config: Arc<Config>,
+    remote_addr: SocketAddr,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = req.method().clone();
+    let uri = req.uri().clone();
+
+    match forward_request(&client, &config.upstream, remote_addr, req).await {
+        Ok(response) => Ok(response),
+        Err(e) => {
+            eprintln!("{} {} -> upstream error: {}", method, uri, e);
+            let mut response = Response::new(Body::from("bad gateway\n"));
+            *response.status_mut() = StatusCode::BAD_GATEWAY;
+            Ok(response)
+        }
     }
 }
 
+async fn run_server(config: Config, shutdown: oneshot::Receiver<()>) -> Result<(), hyper::Error> {
+    let addr = config.listen;
+    let config = Arc::new(config);
+    let client = Client::new();
+
+    let make_svc = make_service_fn(move |socket: &AddrStream| {
+        let client = client.clone();
+        let config = config.clone();
+        let remote_addr = socket.remote_addr();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle_request(client.clone(), config.clone(), remote_addr, req)
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_svc);
+    println!("Listening on {}", server.local_addr());
+
+    server
+        .with_graceful_shutdown(async {
+            shutdown.await.ok();
+        })
+        .await
+}
+
 #[tokio::main]
 async fn main() {
-    let addr = "0.0.0.0:8080".to_socket_addrs().unwrap().next().unwrap();
+    let config = Config::from_args().unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+    println!("Forwarding to {}", config.upstream);
+
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
-    let server = run_server(addr, shutdown_rx);
-    println!("Listening on {}", addr);
-    if let Err(e) = server.await {
+    tokio::spawn(async move {
+        tokio::signal::ctrl_c().await.ok();
+        shutdown_tx.send(()).ok();
+    });
+
+    if let Err(e) = run_server(config, shutdown_rx).await {
         eprintln!("server error: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(s: &str) -> Uri {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn upstream_uri_keeps_path_and_query() {
+        let upstream = uri("http://127.0.0.1:3000");
+
+        assert_eq!(
+            upstream_uri(&upstream, &uri("/a/b?c=1&d")).unwrap(),
+            "http://127.0.0.1:3000/a/b?c=1&d"
+        );
+        assert_eq!(
+            upstream_uri(&upstream, &uri("/a/b")).unwrap(),
+            "http://127.0.0.1:3000/a/b"
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn upstream_uri_prefixes_base_path() {
+        let target = uri("/a/b?c");
+
+        assert_eq!(
+            upstream_uri(&uri("http://backend/base"), &target).unwrap(),
+            "http://backend/base/a/b?c"
+        );
+        assert_eq!(
+            upstream_uri(&uri("http://backend/base/"), &target).unwrap(),
+            "http://backend/base/a/b?c"
+        );
+    }
+
+    #[test]
+    fn upstream_uri_uses_target_of_absolute_form() {
+        // HTTP/2 and proxy-style requests carry scheme and authority, only
+        // their path and query are kept.
+        assert_eq!(
+            upstream_uri(&uri("http://backend"), &uri("http://example.com/x?y")).unwrap(),
+            "http://backend/x?y"
+        );
+        assert_eq!(
+            upstream_uri(&uri("http://backend/base"), &uri("http://example.com")).unwrap(),
+            "http://backend/base/"
+        );
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strip_hop_by_hop_removes_connection_listed_headers() {
+        let mut headers = headers(&[
+            ("connection", "keep-alive, X-Internal"),
+            ("keep-alive", "timeout=5"),
+            ("proxy-connection", "keep-alive"),
+            ("x-internal", "secret"),
+            ("te", "trailers"),
+            ("accept", "*/*"),
+        ]);
+        strip_hop_by_hop(&mut headers);
+
+        assert_eq!(headers, self::headers(&[("accept", "*/*")]));
+    }
+
+    #[test]
+    fn add_forwarded_headers_appends_to_chain() {
+        let mut headers = headers(&[
+            ("x-forwarded-for", "203.0.113.7"),
+            ("forwarded", "for=203.0.113.7"),
+        ]);
+        let host = HeaderValue::from_static("example.com");
+        add_forwarded_headers(&mut headers, "192.0.2.1".parse().unwrap(), Some(&host));
+
+        assert_eq!(headers["x-forwarded-for"], "203.0.113.7, 192.0.2.1");
+        assert_eq!(headers["x-forwarded-host"], "example.com");
+        assert_eq!(headers["x-forwarded-proto"], "http");
+        assert_eq!(
+            headers["forwarded"],
+            "for=203.0.113.7, for=192.0.2.1;proto=http;host=example.com"
+        );
+    }
+
+    #[test]
+    fn add_forwarded_headers_quotes_ipv6() {
+        let mut headers = headers(&[("x-forwarded-host", "spoofed.example")]);
+        add_forwarded_headers(&mut headers, "::1".parse().unwrap(), None);
+
+        assert_eq!(headers["x-forwarded-for"], "::1");
+        assert_eq!(headers["forwarded"], "for=\"[::1]\";proto=http");
+        assert!(!headers.contains_key("x-forwarded-host"));
+    }
+
+    #[test]
+    fn forwarded_value_quotes_non_tokens() {
+        assert_eq!(forwarded_value("example.com"), "example.com");
+        assert_eq!(forwarded_value("example.com:8080"), "\"example.com:8080\"");
+        assert_eq!(forwarded_value(""), "\"\"");
+        assert_eq!(forwarded_value("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}